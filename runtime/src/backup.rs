@@ -0,0 +1,213 @@
+//! Operator backup of the service identity as Shamir shares, and restore from those shares.
+//!
+//! The shared secret is a JSON `KeyBackup`: the active service key, the HD seed tenant keys are
+//! derived from, every retired key and the key history. A restore brings all of them back, so
+//! tenant addresses, old envelopes and the key transition chain survive the loss of the disk.
+
+use crate::config::ServiceConfig;
+use crate::errors::{ZkpError, ZkpResult};
+use crate::ethereum;
+use crate::hd;
+use crate::keyring::{self, KeyHistory, KeyRing};
+use crate::keystore;
+use crate::shamir::{self, KeyShare};
+use secp256k1::{PublicKey, Secp256k1, SecretKey as SecpSecretKey};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KeyBackup {
+    secret_key: String,
+    hd_seed: String,
+    /// Retired keys by version, so envelopes sealed to them can still be opened
+    retired_keys: BTreeMap<u32, String>,
+    history: KeyHistory,
+}
+
+/// Split the service identity into `shares` shares, any `threshold` of which rebuild it
+pub fn split_keystore(config: &ServiceConfig, threshold: u8, shares: u8) -> ZkpResult<Vec<KeyShare>> {
+    let keyring = KeyRing::open(&config.keystore_path, &config.keystore_passphrase)?;
+    let secret_key = *keyring.active_key();
+    let hd_seed = keystore::load_sized_secret(&config.hd_seed_path, &config.keystore_passphrase, hd::SEED_LEN)?;
+
+    let active_version = keyring.active_version();
+    let mut retired_keys = BTreeMap::new();
+    for info in &keyring.history().keys {
+        if info.version == active_version {
+            continue;
+        }
+        retired_keys.insert(info.version, hex::encode(keyring.key(info.version)?.secret_bytes()));
+    }
+
+    let backup = KeyBackup {
+        secret_key: hex::encode(secret_key.secret_bytes()),
+        hd_seed: hex::encode(&hd_seed),
+        retired_keys,
+        history: keyring.history().clone(),
+    };
+    let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &secret_key);
+    shamir::split(
+        &serde_json::to_vec(&backup)?,
+        threshold,
+        shares,
+        &ethereum::compressed_public_key_hex(&public_key),
+    )
+}
+
+/// Rebuild the service identity from shares and write it next to the configured keystore.
+///
+/// The rebuilt key must match `expected_public_key` (or, if none is given, the public key the
+/// shares are tagged with). An existing keystore is never overwritten, and an existing HD seed
+/// must be the one in the backup.
+pub fn restore_keystore(
+    config: &ServiceConfig,
+    shares: &[KeyShare],
+    expected_public_key: Option<&str>,
+) -> ZkpResult<PublicKey> {
+    if config.keystore_path.try_exists()? {
+        return Err(ZkpError::KeystoreError(format!(
+            "Refusing to restore over existing keystore {}",
+            config.keystore_path.display()
+        )));
+    }
+
+    let tagged_public_key = shares
+        .first()
+        .map(|share| share.public_key.as_str())
+        .ok_or_else(|| ZkpError::InvalidInput("No key shares provided".to_string()))?;
+    let expected = parse_public_key(expected_public_key.unwrap_or(tagged_public_key))?;
+
+    let backup: KeyBackup = serde_json::from_slice(&shamir::combine(shares)?)
+        .map_err(|e| ZkpError::KeystoreError(format!("Key shares do not rebuild a valid backup: {}", e)))?;
+    let secret_key = parse_secret_key(&backup.secret_key)?;
+    let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &secret_key);
+    if public_key != expected {
+        return Err(ZkpError::KeystoreError(format!(
+            "Restored key {} does not match expected public key {}",
+            ethereum::compressed_public_key_hex(&public_key),
+            ethereum::compressed_public_key_hex(&expected)
+        )));
+    }
+    let hd_seed = hex::decode(&backup.hd_seed)
+        .ok()
+        .filter(|seed| seed.len() == hd::SEED_LEN)
+        .ok_or_else(|| ZkpError::KeystoreError("Backup holds an invalid HD seed".to_string()))?;
+
+    if config.hd_seed_path.try_exists()? {
+        let existing = keystore::load_sized_secret(&config.hd_seed_path, &config.keystore_passphrase, hd::SEED_LEN)?;
+        if existing != hd_seed {
+            return Err(ZkpError::KeystoreError(format!(
+                "Existing HD seed {} differs from the one in the backup",
+                config.hd_seed_path.display()
+            )));
+        }
+    } else {
+        keystore::save_secret(&config.hd_seed_path, &hd_seed, &config.keystore_passphrase, None)?;
+    }
+    for (version, key) in &backup.retired_keys {
+        let archive = keyring::archive_path(&config.keystore_path, *version);
+        if !archive.try_exists()? {
+            keystore::save(&archive, &parse_secret_key(key)?, &config.keystore_passphrase)?;
+        }
+    }
+    keyring::restore_history(&config.keystore_path, &backup.history)?;
+    // Written last: an existing keystore is what marks the restore as done
+    keystore::save(&config.keystore_path, &secret_key, &config.keystore_passphrase)?;
+    Ok(public_key)
+}
+
+/// Read shares from a file holding one share JSON object per line
+pub fn read_shares(path: &Path) -> ZkpResult<Vec<KeyShare>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(ZkpError::from))
+        .collect()
+}
+
+fn parse_public_key(value: &str) -> ZkpResult<PublicKey> {
+    PublicKey::from_slice(&ethereum::decode_hex_bytes(value)?)
+        .map_err(|e| ZkpError::InvalidInput(format!("Invalid public key {}: {}", value, e)))
+}
+
+fn parse_secret_key(value: &str) -> ZkpResult<SecpSecretKey> {
+    SecpSecretKey::from_slice(&ethereum::decode_hex_bytes(value)?)
+        .map_err(|e| ZkpError::KeystoreError(format!("Backup holds an invalid key: {}", e)))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::RetentionPolicy;
+    use crate::keystore::tests::TempDir;
+
+    pub(crate) fn test_config(dir: &Path) -> ServiceConfig {
+        ServiceConfig {
+            mock_mode: true,
+            keystore_path: dir.join("service-key.json"),
+            keystore_passphrase: "passphrase".to_string(),
+            hd_seed_path: dir.join("hd-seed.json"),
+            key_restore_shares: None,
+            key_restore_public_key: None,
+            admin_token: None,
+            session_ttl_secs: 60,
+            session_max_count: 10,
+            task_store_path: None,
+            retention: RetentionPolicy {
+                max_age_secs: 60,
+                max_count: None,
+                max_bytes: None,
+                tombstone_secs: 60,
+                sweep_interval_secs: 60,
+            },
+            task_queue_capacity: 1,
+            proof_timeout_max_secs: 60,
+            verify_concurrency: 1,
+            priority_aging_secs: 30,
+            webhook_max_attempts: 1,
+            webhook_timeout_secs: 1,
+        }
+    }
+
+    #[test]
+    fn split_and_restore_round_trip() {
+        let source_dir = TempDir::new();
+        let source = test_config(&source_dir.0);
+        let mut keyring = KeyRing::open(&source.keystore_path, &source.keystore_passphrase).unwrap();
+        let seed = keystore::load_or_create_secret(&source.hd_seed_path, &source.keystore_passphrase, hd::SEED_LEN)
+            .unwrap();
+        let retired_key = *keyring.active_key();
+        keyring.rotate().unwrap();
+
+        let shares = split_keystore(&source, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        // One share short of the threshold rebuilds nothing
+        let short_dir = TempDir::new();
+        let short = test_config(&short_dir.0);
+        assert!(restore_keystore(&short, &shares[..2], None).is_err());
+        assert!(!short.keystore_path.exists());
+
+        let target_dir = TempDir::new();
+        let target = test_config(&target_dir.0);
+        let subset = [shares[1].clone(), shares[3].clone(), shares[4].clone()];
+        restore_keystore(&target, &subset, None).unwrap();
+
+        let restored = KeyRing::open(&target.keystore_path, &target.keystore_passphrase).unwrap();
+        assert_eq!(restored.active_key(), keyring.active_key());
+        assert_eq!(restored.active_version(), 2);
+        assert_eq!(restored.history().transitions.len(), 1);
+        assert_eq!(
+            keystore::load_sized_secret(&target.hd_seed_path, &target.keystore_passphrase, hd::SEED_LEN).unwrap(),
+            seed
+        );
+        assert_eq!(
+            keystore::load(&keyring::archive_path(&target.keystore_path, 1), &target.keystore_passphrase).unwrap(),
+            retired_key
+        );
+
+        // Never over an existing keystore
+        assert!(restore_keystore(&target, &subset, None).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::errors::{ZkpError, ZkpResult};
+use std::path::PathBuf;
+
+const DEFAULT_KEYSTORE_PATH: &str = "/zkservice/keystore/service-key.json";
+const DEFAULT_SESSION_TTL_SECS: u64 = 900;
+const DEFAULT_SESSION_MAX_COUNT: usize = 10_000;
+const DEFAULT_TASK_RETENTION_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_TASK_TOMBSTONE_SECS: u64 = 7 * 24 * 60 * 60;
+const DEFAULT_TASK_SWEEP_INTERVAL_SECS: u64 = 60;
+const DEFAULT_TASK_QUEUE_CAPACITY: usize = 1024;
+const DEFAULT_PROOF_TIMEOUT_MAX_SECS: u64 = 30 * 60;
+const DEFAULT_PRIORITY_AGING_SECS: u64 = 30;
+const DEFAULT_WEBHOOK_MAX_ATTEMPTS: u32 = 8;
+const DEFAULT_WEBHOOK_TIMEOUT_SECS: u64 = 10;
+
+/// Runtime configuration, read from environment variables at startup.
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    pub mock_mode: bool,
+    pub keystore_path: PathBuf,
+    pub keystore_passphrase: String,
+    pub hd_seed_path: PathBuf,
+    pub key_restore_shares: Option<PathBuf>,
+    /// Public key the restored key must have; required when restoring from shares at startup
+    pub key_restore_public_key: Option<String>,
+    pub admin_token: Option<String>,
+    pub session_ttl_secs: u64,
+    /// Handshakes are refused while this many sessions are live
+    pub session_max_count: usize,
+    /// Directory for the file task store, or `None` to keep tasks in memory only
+    pub task_store_path: Option<PathBuf>,
+    pub retention: RetentionPolicy,
+    pub task_queue_capacity: usize,
+    /// Longest a single task may spend proving; also the timeout for requests that set none
+    pub proof_timeout_max_secs: u64,
+    /// Proof verifications (`bb` processes) allowed to run at once
+    pub verify_concurrency: usize,
+    /// Queued tasks move up one priority class for every this many seconds they wait
+    pub priority_aging_secs: u64,
+    pub webhook_max_attempts: u32,
+    pub webhook_timeout_secs: u64,
+}
+
+/// How long finished (completed or failed) tasks are kept. Tasks beyond any limit are replaced
+/// by a small "expired" marker, which is itself dropped after `tombstone_secs`.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub max_age_secs: u64,
+    pub max_count: Option<usize>,
+    pub max_bytes: Option<u64>,
+    pub tombstone_secs: u64,
+    pub sweep_interval_secs: u64,
+}
+
+impl ServiceConfig {
+    pub fn from_env() -> ZkpResult<Self> {
+        let mock_mode = std::env::var("MOCK_MODE").unwrap_or_else(|_| "false".to_string()) == "true";
+        let keystore_path: PathBuf = std::env::var("KEYSTORE_PATH")
+            .unwrap_or_else(|_| DEFAULT_KEYSTORE_PATH.to_string())
+            .into();
+        let hd_seed_path = std::env::var("HD_SEED_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| keystore_path.with_file_name("hd-seed.json"));
+        let keystore_passphrase = std::env::var("KEYSTORE_PASSPHRASE")
+            .ok()
+            .filter(|p| !p.is_empty())
+            .ok_or_else(|| ZkpError::KeystoreError("KEYSTORE_PASSPHRASE must be set".to_string()))?;
+        let key_restore_shares = std::env::var("KEY_RESTORE_SHARES")
+            .ok()
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from);
+        let key_restore_public_key = std::env::var("KEY_RESTORE_PUBLIC_KEY").ok().filter(|k| !k.is_empty());
+        let admin_token = std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty());
+        let session_ttl_secs = parse_env("SESSION_TTL_SECS")?.unwrap_or(DEFAULT_SESSION_TTL_SECS);
+        let session_max_count = parse_env("SESSION_MAX_COUNT")?.unwrap_or(DEFAULT_SESSION_MAX_COUNT);
+        let task_store_path = match std::env::var("TASK_STORE").as_deref() {
+            Ok("memory") => None,
+            Ok("file") | Ok("") | Err(_) => Some(
+                std::env::var("TASK_STORE_PATH")
+                    .ok()
+                    .filter(|p| !p.is_empty())
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| keystore_path.with_file_name("tasks")),
+            ),
+            Ok(other) => {
+                return Err(ZkpError::InvalidInput(format!(
+                    "Invalid TASK_STORE: {} (expected file or memory)",
+                    other
+                )))
+            }
+        };
+
+        let retention = RetentionPolicy {
+            max_age_secs: parse_env("TASK_RETENTION_SECS")?.unwrap_or(DEFAULT_TASK_RETENTION_SECS),
+            max_count: parse_env("TASK_RETENTION_MAX_COUNT")?,
+            max_bytes: parse_env("TASK_RETENTION_MAX_BYTES")?,
+            tombstone_secs: parse_env("TASK_TOMBSTONE_SECS")?.unwrap_or(DEFAULT_TASK_TOMBSTONE_SECS),
+            sweep_interval_secs: parse_env("TASK_SWEEP_INTERVAL_SECS")?
+                .unwrap_or(DEFAULT_TASK_SWEEP_INTERVAL_SECS)
+                .max(1),
+        };
+        let task_queue_capacity = parse_env("TASK_QUEUE_CAPACITY")?.unwrap_or(DEFAULT_TASK_QUEUE_CAPACITY);
+        let proof_timeout_max_secs = parse_env("PROOF_TIMEOUT_MAX_SECS")?
+            .unwrap_or(DEFAULT_PROOF_TIMEOUT_MAX_SECS)
+            .max(1);
+        let verify_concurrency = parse_env("VERIFY_CONCURRENCY")?.unwrap_or_else(num_cpus::get).max(1);
+        let priority_aging_secs = parse_env("TASK_PRIORITY_AGING_SECS")?
+            .unwrap_or(DEFAULT_PRIORITY_AGING_SECS)
+            .max(1);
+        let webhook_max_attempts = parse_env("WEBHOOK_MAX_ATTEMPTS")?
+            .unwrap_or(DEFAULT_WEBHOOK_MAX_ATTEMPTS)
+            .max(1);
+        let webhook_timeout_secs = parse_env("WEBHOOK_TIMEOUT_SECS")?
+            .unwrap_or(DEFAULT_WEBHOOK_TIMEOUT_SECS)
+            .max(1);
+
+        Ok(Self {
+            mock_mode,
+            keystore_path,
+            keystore_passphrase,
+            hd_seed_path,
+            key_restore_shares,
+            key_restore_public_key,
+            admin_token,
+            session_ttl_secs,
+            session_max_count,
+            task_store_path,
+            retention,
+            task_queue_capacity,
+            proof_timeout_max_secs,
+            verify_concurrency,
+            priority_aging_secs,
+            webhook_max_attempts,
+            webhook_timeout_secs,
+        })
+    }
+}
+
+// Numeric setting; unset or empty means "use the default"
+fn parse_env<T: std::str::FromStr>(name: &str) -> ZkpResult<Option<T>> {
+    match std::env::var(name).ok().filter(|v| !v.is_empty()) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| ZkpError::InvalidInput(format!("Invalid {}: {}", name, value))),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,358 @@
+//! EIP-712 typed structured data hashing.
+//!
+//! Follows the encoding used by `eth_signTypedData_v4`: struct members are encoded in
+//! declaration order, dynamic values and arrays are hashed, and referenced struct types
+//! are appended to `encodeType` in alphabetical order.
+
+use crate::errors::{ZkpError, ZkpResult};
+use crate::ethereum::{decode_hex_bytes, keccak256, parse_address, parse_uint256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+const DOMAIN_TYPE: &str = "EIP712Domain";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypedDataField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    pub types: BTreeMap<String, Vec<TypedDataField>>,
+    pub primary_type: String,
+    pub domain: serde_json::Map<String, Value>,
+    pub message: Value,
+}
+
+impl TypedData {
+    /// keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(message))
+    pub fn signing_hash(&self) -> ZkpResult<[u8; 32]> {
+        let mut types = self.types.clone();
+        if !types.contains_key(DOMAIN_TYPE) {
+            types.insert(DOMAIN_TYPE.to_string(), infer_domain_type(&self.domain));
+        }
+        let encoder = Encoder { types: &types };
+
+        let domain_separator = encoder.hash_struct(DOMAIN_TYPE, &Value::Object(self.domain.clone()))?;
+
+        let mut preimage = Vec::with_capacity(66);
+        preimage.extend_from_slice(&[0x19, 0x01]);
+        preimage.extend_from_slice(&domain_separator);
+        if self.primary_type != DOMAIN_TYPE {
+            preimage.extend_from_slice(&encoder.hash_struct(&self.primary_type, &self.message)?);
+        }
+        Ok(keccak256(&preimage))
+    }
+}
+
+// Clients often omit EIP712Domain from `types`; derive it from the fields that are present
+fn infer_domain_type(domain: &serde_json::Map<String, Value>) -> Vec<TypedDataField> {
+    [
+        ("name", "string"),
+        ("version", "string"),
+        ("chainId", "uint256"),
+        ("verifyingContract", "address"),
+        ("salt", "bytes32"),
+    ]
+    .into_iter()
+    .filter(|(name, _)| domain.contains_key(*name))
+    .map(|(name, kind)| TypedDataField {
+        name: name.to_string(),
+        kind: kind.to_string(),
+    })
+    .collect()
+}
+
+struct Encoder<'a> {
+    types: &'a BTreeMap<String, Vec<TypedDataField>>,
+}
+
+impl Encoder<'_> {
+    fn fields(&self, type_name: &str) -> ZkpResult<&Vec<TypedDataField>> {
+        self.types
+            .get(type_name)
+            .ok_or_else(|| ZkpError::InvalidInput(format!("Unknown EIP-712 type: {}", type_name)))
+    }
+
+    fn collect_dependencies(&self, type_name: &str, found: &mut BTreeSet<String>) -> ZkpResult<()> {
+        if found.contains(type_name) {
+            return Ok(());
+        }
+        found.insert(type_name.to_string());
+        for field in self.fields(type_name)? {
+            let base = base_type(&field.kind);
+            if self.types.contains_key(base) {
+                self.collect_dependencies(base, found)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_type(&self, type_name: &str) -> ZkpResult<String> {
+        let mut dependencies = BTreeSet::new();
+        self.collect_dependencies(type_name, &mut dependencies)?;
+        dependencies.remove(type_name);
+
+        let mut encoded = String::new();
+        for name in std::iter::once(type_name).chain(dependencies.iter().map(String::as_str)) {
+            let members: Vec<String> = self
+                .fields(name)?
+                .iter()
+                .map(|f| format!("{} {}", f.kind, f.name))
+                .collect();
+            encoded.push_str(&format!("{}({})", name, members.join(",")));
+        }
+        Ok(encoded)
+    }
+
+    fn hash_struct(&self, type_name: &str, value: &Value) -> ZkpResult<[u8; 32]> {
+        let object = value.as_object().ok_or_else(|| {
+            ZkpError::InvalidInput(format!("Expected an object for EIP-712 type {}", type_name))
+        })?;
+
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(&keccak256(self.encode_type(type_name)?.as_bytes()));
+        for field in self.fields(type_name)? {
+            let field_value = object.get(&field.name).ok_or_else(|| {
+                ZkpError::InvalidInput(format!("Missing field {} of EIP-712 type {}", field.name, type_name))
+            })?;
+            encoded.extend_from_slice(&self.encode_value(&field.kind, field_value)?);
+        }
+        Ok(keccak256(&encoded))
+    }
+
+    fn encode_value(&self, kind: &str, value: &Value) -> ZkpResult<[u8; 32]> {
+        if let Some((element, length)) = split_array_type(kind) {
+            let items = value
+                .as_array()
+                .ok_or_else(|| ZkpError::InvalidInput(format!("Expected an array for EIP-712 type {}", kind)))?;
+            if let Some(length) = length
+                && items.len() != length
+            {
+                return Err(ZkpError::InvalidInput(format!(
+                    "Expected {} elements for EIP-712 type {}",
+                    length, kind
+                )));
+            }
+            let mut encoded = Vec::with_capacity(items.len() * 32);
+            for item in items {
+                encoded.extend_from_slice(&self.encode_value(element, item)?);
+            }
+            return Ok(keccak256(&encoded));
+        }
+
+        if self.types.contains_key(kind) {
+            return self.hash_struct(kind, value);
+        }
+
+        encode_atomic(kind, value)
+    }
+}
+
+fn encode_atomic(kind: &str, value: &Value) -> ZkpResult<[u8; 32]> {
+    let invalid = || ZkpError::InvalidInput(format!("Invalid value for EIP-712 type {}: {}", kind, value));
+
+    match kind {
+        "string" => Ok(keccak256(value.as_str().ok_or_else(invalid)?.as_bytes())),
+        "bytes" => Ok(keccak256(&decode_hex_bytes(value.as_str().ok_or_else(invalid)?)?)),
+        "bool" => {
+            let mut word = [0u8; 32];
+            word[31] = value.as_bool().ok_or_else(invalid)? as u8;
+            Ok(word)
+        }
+        "address" => {
+            let mut word = [0u8; 32];
+            word[12..].copy_from_slice(&parse_address(value.as_str().ok_or_else(invalid)?)?);
+            Ok(word)
+        }
+        _ => {
+            if let Some(size) = kind.strip_prefix("bytes") {
+                let size: usize = size.parse().map_err(|_| invalid())?;
+                let bytes = decode_hex_bytes(value.as_str().ok_or_else(invalid)?)?;
+                if !(1..=32).contains(&size) || bytes.len() != size {
+                    return Err(invalid());
+                }
+                let mut word = [0u8; 32];
+                word[..size].copy_from_slice(&bytes);
+                Ok(word)
+            } else if let Some(bits) = kind.strip_prefix("uint") {
+                let bits = parse_bits(bits).ok_or_else(invalid)?;
+                let word = parse_uint256(value)?;
+                if !fits_unsigned(&word, bits) {
+                    return Err(invalid());
+                }
+                Ok(word)
+            } else if let Some(bits) = kind.strip_prefix("int") {
+                let bits = parse_bits(bits).ok_or_else(invalid)?;
+                encode_int(value, bits).ok_or_else(invalid)
+            } else {
+                Err(ZkpError::InvalidInput(format!("Unknown EIP-712 type: {}", kind)))
+            }
+        }
+    }
+}
+
+/// Two's complement encoding of a signed integer, checked against the type's width
+fn encode_int(value: &Value, bits: usize) -> Option<[u8; 32]> {
+    let (negative, magnitude) = match value {
+        Value::Number(n) => {
+            let n = n.as_i64()?;
+            let mut word = [0u8; 32];
+            word[24..].copy_from_slice(&n.unsigned_abs().to_be_bytes());
+            (n < 0, word)
+        }
+        Value::String(s) => match s.strip_prefix('-') {
+            Some(rest) => (true, parse_uint256(&Value::String(rest.to_string())).ok()?),
+            None => (false, parse_uint256(value).ok()?),
+        },
+        _ => return None,
+    };
+
+    // Positive values must be below 2^(bits-1), negative ones at most 2^(bits-1) in magnitude
+    if !fits_unsigned(&magnitude, bits - 1) {
+        let mut limit = [0u8; 32];
+        limit[31 - (bits - 1) / 8] = 1 << ((bits - 1) % 8);
+        if !negative || magnitude != limit {
+            return None;
+        }
+    }
+
+    if !negative {
+        return Some(magnitude);
+    }
+    let mut word = magnitude.map(|b| !b);
+    for byte in word.iter_mut().rev() {
+        let (sum, overflow) = byte.overflowing_add(1);
+        *byte = sum;
+        if !overflow {
+            break;
+        }
+    }
+    Some(word)
+}
+
+fn parse_bits(bits: &str) -> Option<usize> {
+    if bits.is_empty() {
+        return Some(256);
+    }
+    let bits: usize = bits.parse().ok()?;
+    (bits.is_multiple_of(8) && (8..=256).contains(&bits)).then_some(bits)
+}
+
+fn fits_unsigned(word: &[u8; 32], bits: usize) -> bool {
+    let full_bytes = (256 - bits) / 8;
+    let partial_bits = (256 - bits) % 8;
+    if word[..full_bytes].iter().any(|b| *b != 0) {
+        return false;
+    }
+    partial_bits == 0 || word[full_bytes] >> (8 - partial_bits) == 0
+}
+
+// "Foo[2][]" -> ("Foo[2]", None), "Foo[2]" -> ("Foo", Some(2))
+fn split_array_type(kind: &str) -> Option<(&str, Option<usize>)> {
+    let inner = kind.strip_suffix(']')?;
+    let open = inner.rfind('[')?;
+    let length = &inner[open + 1..];
+    let length = if length.is_empty() { None } else { Some(length.parse().ok()?) };
+    Some((&inner[..open], length))
+}
+
+fn base_type(kind: &str) -> &str {
+    kind.split('[').next().unwrap_or(kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The `Mail` example from the EIP-712 specification
+    fn mail() -> TypedData {
+        serde_json::from_value(serde_json::json!({
+            "types": {
+                "EIP712Domain": [
+                    { "name": "name", "type": "string" },
+                    { "name": "version", "type": "string" },
+                    { "name": "chainId", "type": "uint256" },
+                    { "name": "verifyingContract", "type": "address" },
+                ],
+                "Person": [
+                    { "name": "name", "type": "string" },
+                    { "name": "wallet", "type": "address" },
+                ],
+                "Mail": [
+                    { "name": "from", "type": "Person" },
+                    { "name": "to", "type": "Person" },
+                    { "name": "contents", "type": "string" },
+                ],
+            },
+            "primaryType": "Mail",
+            "domain": {
+                "name": "Ether Mail",
+                "version": "1",
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC",
+            },
+            "message": {
+                "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+                "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+                "contents": "Hello, Bob!",
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn mail_example() {
+        let typed_data = mail();
+        let encoder = Encoder { types: &typed_data.types };
+
+        let encoded_type = encoder.encode_type("Mail").unwrap();
+        assert_eq!(encoded_type, "Mail(Person from,Person to,string contents)Person(string name,address wallet)");
+        assert_eq!(
+            hex::encode(keccak256(encoded_type.as_bytes())),
+            "a0cedeb2dc280ba39b857546d74f5549c3a1d7bdc2dd96bf881f76108e23dac2"
+        );
+        assert_eq!(
+            hex::encode(encoder.hash_struct("Mail", &typed_data.message).unwrap()),
+            "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+        );
+        assert_eq!(
+            hex::encode(encoder.hash_struct(DOMAIN_TYPE, &Value::Object(typed_data.domain.clone())).unwrap()),
+            "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+        );
+        assert_eq!(
+            hex::encode(typed_data.signing_hash().unwrap()),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+    }
+
+    #[test]
+    fn mail_example_signature() {
+        let secret_key = secp256k1::SecretKey::from_slice(&keccak256(b"cow")).unwrap();
+        let signature =
+            crate::ethereum::sign_digest(&secp256k1::Secp256k1::new(), &secret_key, &mail().signing_hash().unwrap())
+                .unwrap();
+        assert_eq!(
+            hex::encode(signature),
+            concat!(
+                "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d",
+                "07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562",
+                "1c",
+            )
+        );
+    }
+
+    #[test]
+    fn domain_type_is_inferred() {
+        let mut typed_data = mail();
+        typed_data.types.remove(DOMAIN_TYPE);
+        assert_eq!(
+            hex::encode(typed_data.signing_hash().unwrap()),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+    }
+}
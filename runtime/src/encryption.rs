@@ -0,0 +1,140 @@
+//! Registry of the encryption suites clients can use to send secrets to the service.
+//!
+//! Every envelope names its suite; envelopes without one are treated as secp256k1 ECIES,
+//! which was the only suite before the registry existed.
+//!
+//! Proof inputs are bound to `PROOF_INPUT_CONTEXT`, so an envelope meant for a proof cannot be
+//! opened by general decryption (`/decrypt-input`, which uses an empty context) and vice versa.
+//! ECIES carries the context as AES-GCM associated data. libsodium sealed boxes have no associated
+//! data, so there the plaintext starts with the context instead: a proof input sealed box holds
+//! `PROOF_INPUT_CONTEXT ‖ input JSON`, and general decryption refuses plaintexts that start with
+//! a known context.
+
+use crate::ecies;
+use crate::errors::{ZkpError, ZkpResult};
+use crate::sealed_box;
+use secp256k1::{Secp256k1, SecretKey as SecpSecretKey};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Associated data for proof input envelopes (`ProofRequest.encrypted_input`)
+pub const PROOF_INPUT_CONTEXT: &[u8] = b"zkpruntime proof input v1";
+
+/// Associated data for the input commitment salt the service seals next to each proof input
+pub const INPUT_SALT_CONTEXT: &[u8] = b"zkpruntime input salt v1";
+
+const CONTEXTS: [&[u8]; 2] = [PROOF_INPUT_CONTEXT, INPUT_SALT_CONTEXT];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionSuite {
+    Secp256k1Ecies,
+    X25519XChaCha20Poly1305,
+}
+
+impl EncryptionSuite {
+    pub const ALL: [EncryptionSuite; 2] = [EncryptionSuite::Secp256k1Ecies, EncryptionSuite::X25519XChaCha20Poly1305];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            EncryptionSuite::Secp256k1Ecies => "secp256k1-ecies-aes256gcm",
+            EncryptionSuite::X25519XChaCha20Poly1305 => "x25519-xchacha20poly1305",
+        }
+    }
+
+    /// Look up a suite by identifier; `None` selects the default secp256k1 ECIES suite
+    pub fn from_id(id: Option<&str>) -> ZkpResult<Self> {
+        match id {
+            None => Ok(EncryptionSuite::Secp256k1Ecies),
+            Some(id) => Self::ALL
+                .into_iter()
+                .find(|suite| suite.id() == id)
+                .ok_or_else(|| ZkpError::InvalidInput(format!("Unsupported encryption suite: {}", id))),
+        }
+    }
+
+    /// The hex public key clients encrypt to for this suite
+    pub fn public_key_hex(self, secp: &Secp256k1<secp256k1::All>, service_key: &SecpSecretKey) -> ZkpResult<String> {
+        let public_key = match self {
+            EncryptionSuite::Secp256k1Ecies => {
+                secp256k1::PublicKey::from_secret_key(secp, service_key).serialize_uncompressed().to_vec()
+            }
+            EncryptionSuite::X25519XChaCha20Poly1305 => {
+                x25519_dalek::PublicKey::from(&sealed_box::derive_secret(service_key)?).as_bytes().to_vec()
+            }
+        };
+        Ok(format!("0x{}", hex::encode(public_key)))
+    }
+
+    /// Open a payload addressed to the service key; `context` must match the one it was
+    /// sealed with
+    pub fn decrypt(self, service_key: &SecpSecretKey, payload: &[u8], context: &[u8]) -> ZkpResult<Vec<u8>> {
+        match self {
+            EncryptionSuite::Secp256k1Ecies => ecies::decrypt(service_key, payload, context),
+            EncryptionSuite::X25519XChaCha20Poly1305 => {
+                let plaintext = sealed_box::open(&sealed_box::derive_secret(service_key)?, payload)?;
+                strip_context(plaintext, context)
+            }
+        }
+    }
+}
+
+// Check and remove the context prefix of a sealed box plaintext
+fn strip_context(mut plaintext: Vec<u8>, context: &[u8]) -> ZkpResult<Vec<u8>> {
+    if context.is_empty() {
+        if CONTEXTS.iter().any(|context| plaintext.starts_with(context)) {
+            return Err(ZkpError::DecryptionError("Payload is bound to another context".to_string()));
+        }
+        return Ok(plaintext);
+    }
+    if !plaintext.starts_with(context) {
+        return Err(ZkpError::DecryptionError("Payload is not bound to this context".to_string()));
+    }
+    plaintext.drain(..context.len());
+    Ok(plaintext)
+}
+
+impl Serialize for EncryptionSuite {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.id())
+    }
+}
+
+impl<'de> Deserialize<'de> for EncryptionSuite {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        Self::from_id(Some(&id)).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proof_inputs_are_domain_separated() {
+        let secp = Secp256k1::new();
+        let (secret_key, public_key) = secp.generate_keypair(&mut rand::thread_rng());
+        let suite = EncryptionSuite::Secp256k1Ecies;
+
+        let proof_input = ecies::encrypt(&public_key, b"{\"x\":1}", PROOF_INPUT_CONTEXT).unwrap();
+        assert!(suite.decrypt(&secret_key, &proof_input, b"").is_err());
+        assert_eq!(suite.decrypt(&secret_key, &proof_input, PROOF_INPUT_CONTEXT).unwrap(), b"{\"x\":1}");
+
+        let general = ecies::encrypt(&public_key, b"secret", b"").unwrap();
+        assert!(suite.decrypt(&secret_key, &general, PROOF_INPUT_CONTEXT).is_err());
+    }
+
+    #[test]
+    fn sealed_box_contexts_are_prefixes() {
+        let service_key = crate::keystore::generate_secret_key().unwrap();
+        let public_key = x25519_dalek::PublicKey::from(&sealed_box::derive_secret(&service_key).unwrap());
+        let suite = EncryptionSuite::X25519XChaCha20Poly1305;
+
+        let proof_input = sealed_box::seal(&public_key, &[PROOF_INPUT_CONTEXT, b"{\"x\":1}"].concat()).unwrap();
+        assert!(suite.decrypt(&service_key, &proof_input, b"").is_err());
+        assert_eq!(suite.decrypt(&service_key, &proof_input, PROOF_INPUT_CONTEXT).unwrap(), b"{\"x\":1}");
+
+        let general = sealed_box::seal(&public_key, b"secret").unwrap();
+        assert!(suite.decrypt(&service_key, &general, PROOF_INPUT_CONTEXT).is_err());
+        assert_eq!(suite.decrypt(&service_key, &general, b"").unwrap(), b"secret");
+    }
+}
@@ -10,12 +10,30 @@ pub enum ZkpError {
     
     #[error("Key generation failed: {0}")]
     KeyGenerationError(String),
+
+    #[error("Keystore error: {0}")]
+    KeystoreError(String),
     
     #[error("State operation failed: {0}")]
     StateError(String),
     
     #[error("Decryption failed: {0}")]
     DecryptionError(String),
+
+    #[error("Encryption failed: {0}")]
+    EncryptionError(String),
+    
+    #[error("Session error: {0}")]
+    SessionError(String),
+    
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
+    #[error("Proof queue is full, retry in {retry_after_secs}s")]
+    QueueFull { retry_after_secs: u64 },
+
+    #[error("Too many open sessions, retry in {retry_after_secs}s")]
+    SessionLimit { retry_after_secs: u64 },
     
     #[error("Invalid input: {0}")]
     InvalidInput(String),
@@ -30,5 +48,28 @@ pub enum ZkpError {
     GitCloneError(String),
 }
 
+impl ZkpError {
+    /// Stable machine-readable code for the error kind, reported with failed tasks
+    pub fn code(&self) -> &'static str {
+        match self {
+            ZkpError::NoirCommandError(_) => "noir_command_error",
+            ZkpError::ProofGenerationError(_) => "proof_generation_error",
+            ZkpError::KeyGenerationError(_) => "key_generation_error",
+            ZkpError::KeystoreError(_) => "keystore_error",
+            ZkpError::StateError(_) => "state_error",
+            ZkpError::DecryptionError(_) => "decryption_error",
+            ZkpError::EncryptionError(_) => "encryption_error",
+            ZkpError::SessionError(_) => "session_error",
+            ZkpError::Timeout(_) => "timeout",
+            ZkpError::QueueFull { .. } => "queue_full",
+            ZkpError::SessionLimit { .. } => "session_limit",
+            ZkpError::InvalidInput(_) => "invalid_input",
+            ZkpError::IoError(_) => "io_error",
+            ZkpError::JsonError(_) => "json_error",
+            ZkpError::GitCloneError(_) => "git_clone_error",
+        }
+    }
+}
+
 pub type ZkpResult<T> = Result<T, ZkpError>;
 
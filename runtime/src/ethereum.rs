@@ -0,0 +1,246 @@
+//! Ethereum hashing and signature helpers shared by the service, keyring and receipts.
+
+use crate::errors::{ZkpError, ZkpResult};
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey as SecpSecretKey, Signing, Verification};
+use sha3::{Digest, Keccak256};
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// EIP-191 personal message hash: keccak256("\x19Ethereum Signed Message:\n" + len + message)
+pub fn personal_message_hash(message: &[u8]) -> [u8; 32] {
+    let prefix = format!("\x19Ethereum Signed Message:\n{}", message.len());
+    let mut hasher = Keccak256::new();
+    hasher.update(prefix.as_bytes());
+    hasher.update(message);
+    hasher.finalize().into()
+}
+
+/// Sign a 32-byte digest and return the Ethereum signature: r (32 bytes) + s (32 bytes) + v (1 byte)
+pub fn sign_digest<C: Signing>(
+    secp: &Secp256k1<C>,
+    secret_key: &SecpSecretKey,
+    digest: &[u8; 32],
+) -> ZkpResult<[u8; 65]> {
+    let message = Message::from_digest_slice(digest)
+        .map_err(|e| ZkpError::StateError(format!("Failed to create message hash: {}", e)))?;
+    let signature = secp.sign_ecdsa_recoverable(&message, secret_key);
+    let (recovery_id, sig_compact) = signature.serialize_compact();
+
+    let mut signature_bytes = [0u8; 65];
+    signature_bytes[..64].copy_from_slice(&sig_compact);
+    // v = recovery ID + 27 for Ethereum
+    signature_bytes[64] = recovery_id.to_i32() as u8 + 27;
+    Ok(signature_bytes)
+}
+
+/// Sign `message` with EIP-191 personal sign and return the 0x-prefixed hex signature
+pub fn sign_personal_message<C: Signing>(
+    secp: &Secp256k1<C>,
+    secret_key: &SecpSecretKey,
+    message: &[u8],
+) -> ZkpResult<String> {
+    let signature = sign_digest(secp, secret_key, &personal_message_hash(message))?;
+    Ok(format!("0x{}", hex::encode(signature)))
+}
+
+/// Recover the signer of a 32-byte digest from a 65-byte r + s + v signature.
+/// Accepts v as 27/28 (Ethereum) or 0/1 (raw recovery ID). Signatures with s above half the curve
+/// order are rejected (EIP-2), so each signature has exactly one valid form.
+pub fn recover_public_key<C: Verification>(
+    secp: &Secp256k1<C>,
+    digest: &[u8; 32],
+    signature: &[u8],
+) -> ZkpResult<PublicKey> {
+    if signature.len() != 65 {
+        return Err(ZkpError::InvalidInput(format!(
+            "Signature must be 65 bytes, got {}",
+            signature.len()
+        )));
+    }
+    let v = match signature[64] {
+        v @ (27 | 28) => v - 27,
+        v @ (0 | 1) => v,
+        v => return Err(ZkpError::InvalidInput(format!("Invalid signature recovery byte: {}", v))),
+    };
+    let recovery_id = RecoveryId::from_i32(v as i32)
+        .map_err(|e| ZkpError::InvalidInput(format!("Invalid recovery ID: {}", e)))?;
+    let signature = RecoverableSignature::from_compact(&signature[..64], recovery_id)
+        .map_err(|e| ZkpError::InvalidInput(format!("Invalid signature: {}", e)))?;
+    let standard = signature.to_standard();
+    let mut normalized = standard;
+    normalized.normalize_s();
+    if normalized != standard {
+        return Err(ZkpError::InvalidInput(
+            "Signature s value is not in the lower half of the curve order".to_string(),
+        ));
+    }
+    let message = Message::from_digest_slice(digest)
+        .map_err(|e| ZkpError::InvalidInput(format!("Invalid digest: {}", e)))?;
+
+    secp.recover_ecdsa(&message, &signature)
+        .map_err(|e| ZkpError::InvalidInput(format!("Failed to recover public key: {}", e)))
+}
+
+/// Ethereum address: last 20 bytes of keccak256 over the uncompressed key without its 0x04 prefix
+pub fn address_from_public_key(public_key: &PublicKey) -> [u8; 20] {
+    let hash = keccak256(&public_key.serialize_uncompressed()[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    address
+}
+
+/// EIP-55 mixed-case checksum encoding
+pub fn format_address(address: &[u8; 20]) -> String {
+    let lower = hex::encode(address);
+    let hash = keccak256(lower.as_bytes());
+    let checksummed: String = lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+            if nibble >= 8 { c.to_ascii_uppercase() } else { c }
+        })
+        .collect();
+    format!("0x{}", checksummed)
+}
+
+pub fn public_key_hex(public_key: &PublicKey) -> String {
+    format!("0x{}", hex::encode(public_key.serialize_uncompressed()))
+}
+
+pub fn compressed_public_key_hex(public_key: &PublicKey) -> String {
+    format!("0x{}", hex::encode(public_key.serialize()))
+}
+
+/// Short key identifier: the first 8 bytes of keccak256 over the compressed key
+pub fn key_fingerprint(public_key: &PublicKey) -> String {
+    format!("0x{}", hex::encode(&keccak256(&public_key.serialize())[..8]))
+}
+
+pub fn decode_hex_bytes(value: &str) -> ZkpResult<Vec<u8>> {
+    hex::decode(value.trim_start_matches("0x"))
+        .map_err(|e| ZkpError::InvalidInput(format!("Invalid hex value {}: {}", value, e)))
+}
+
+pub fn parse_address(value: &str) -> ZkpResult<[u8; 20]> {
+    decode_hex_bytes(value)?
+        .try_into()
+        .map_err(|_| ZkpError::InvalidInput(format!("Invalid address: {}", value)))
+}
+
+/// Parse an unsigned 256-bit integer from a JSON number, a decimal string or a 0x-prefixed hex string.
+/// Returns the big-endian 32-byte encoding.
+pub fn parse_uint256(value: &serde_json::Value) -> ZkpResult<[u8; 32]> {
+    match value {
+        serde_json::Value::Number(n) => {
+            let n = n
+                .as_u64()
+                .ok_or_else(|| ZkpError::InvalidInput(format!("Invalid unsigned integer: {}", n)))?;
+            let mut word = [0u8; 32];
+            word[24..].copy_from_slice(&n.to_be_bytes());
+            Ok(word)
+        }
+        serde_json::Value::String(s) => parse_uint256_str(s),
+        other => Err(ZkpError::InvalidInput(format!("Invalid unsigned integer: {}", other))),
+    }
+}
+
+fn parse_uint256_str(s: &str) -> ZkpResult<[u8; 32]> {
+    let mut word = [0u8; 32];
+    if let Some(hex_digits) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        if hex_digits.is_empty() || hex_digits.len() > 64 {
+            return Err(ZkpError::InvalidInput(format!("Invalid unsigned integer: {}", s)));
+        }
+        let padded = format!("{:0>64}", hex_digits);
+        hex::decode_to_slice(padded, &mut word)
+            .map_err(|_| ZkpError::InvalidInput(format!("Invalid unsigned integer: {}", s)))?;
+        return Ok(word);
+    }
+
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ZkpError::InvalidInput(format!("Invalid unsigned integer: {}", s)));
+    }
+    for digit in s.bytes().map(|b| b - b'0') {
+        // word = word * 10 + digit
+        let mut carry = digit as u16;
+        for byte in word.iter_mut().rev() {
+            let product = *byte as u16 * 10 + carry;
+            *byte = product as u8;
+            carry = product >> 8;
+        }
+        if carry != 0 {
+            return Err(ZkpError::InvalidInput(format!("Integer does not fit in 256 bits: {}", s)));
+        }
+    }
+    Ok(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eip55_checksums() {
+        // Test vectors from the EIP-55 specification
+        for expected in [
+            "0x52908400098527886E0F7030069857D2E4169EE7",
+            "0x8617E340B3D01FA5F11F306F4090FD50E238070D",
+            "0xde709f2102306220921060314715629080e2fb77",
+            "0x27b1fdb04752bbc536007a920d24acb045561c26",
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            assert_eq!(format_address(&parse_address(expected).unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn address_and_fingerprint_of_known_key() {
+        let secp = Secp256k1::new();
+        let mut secret = [0u8; 32];
+        secret[31] = 1;
+        let public_key = PublicKey::from_secret_key(&secp, &SecpSecretKey::from_slice(&secret).unwrap());
+
+        assert_eq!(
+            format_address(&address_from_public_key(&public_key)),
+            "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"
+        );
+        assert_eq!(
+            compressed_public_key_hex(&public_key),
+            "0x0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+        );
+        assert_eq!(key_fingerprint(&public_key), "0xaa61b794ba668ff6");
+    }
+
+    #[test]
+    fn high_s_signatures_are_rejected() {
+        use secp256k1::constants::CURVE_ORDER;
+
+        let secp = Secp256k1::new();
+        let secret_key = SecpSecretKey::from_slice(&[7u8; 32]).unwrap();
+        let digest = keccak256(b"payment");
+        let signature = sign_digest(&secp, &secret_key, &digest).unwrap();
+        let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+        assert_eq!(recover_public_key(&secp, &digest, &signature).unwrap(), public_key);
+
+        // (r, n - s) with the recovery bit flipped is the same signature's malleable twin
+        let mut flipped = signature;
+        let mut borrow = 0u16;
+        for i in (0..32).rev() {
+            let difference = CURVE_ORDER[i] as u16 + 0x100 - signature[32 + i] as u16 - borrow;
+            flipped[32 + i] = difference as u8;
+            borrow = 1 - (difference >> 8);
+        }
+        flipped[64] = 27 + ((signature[64] - 27) ^ 1);
+        let recovery_id = RecoveryId::from_i32(flipped[64] as i32 - 27).unwrap();
+        let twin = RecoverableSignature::from_compact(&flipped[..64], recovery_id).unwrap();
+        let message = Message::from_digest_slice(&digest).unwrap();
+        assert_eq!(secp.recover_ecdsa(&message, &twin).unwrap(), public_key);
+        assert!(recover_public_key(&secp, &digest, &flipped).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! BIP-32 hierarchical deterministic keys for per-tenant signing identities.
+//!
+//! Tenant keys are derived from a dedicated master seed (not from the rotating service key),
+//! so a tenant's address stays the same across service key rotations. The seed is created
+//! together with the first service key and is backed up and restored with it.
+
+use crate::errors::{ZkpError, ZkpResult};
+use crate::ethereum;
+use hmac::{Hmac, Mac};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey as SecpSecretKey};
+use sha2::Sha512;
+
+type HmacSha512 = Hmac<Sha512>;
+
+const HARDENED: u32 = 0x8000_0000;
+pub const SEED_LEN: usize = 64;
+// Every tenant key lives below this hardened prefix
+const TENANT_PATH_PREFIX: &str = "m/44'/60'";
+
+pub struct ExtendedKey {
+    secret_key: SecpSecretKey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    pub fn master(seed: &[u8]) -> ZkpResult<Self> {
+        let (key, chain_code) = hmac_sha512(b"Bitcoin seed", &[seed])?;
+        let secret_key = SecpSecretKey::from_slice(&key)
+            .map_err(|e| ZkpError::KeyGenerationError(format!("Invalid BIP-32 master key: {}", e)))?;
+        Ok(Self { secret_key, chain_code })
+    }
+
+    pub fn secret_key(&self) -> &SecpSecretKey {
+        &self.secret_key
+    }
+
+    /// Derive the key at `path`, e.g. "m/44'/60'/0'/0/0"
+    pub fn derive_path(&self, secp: &Secp256k1<secp256k1::All>, path: &str) -> ZkpResult<Self> {
+        let mut key = Self {
+            secret_key: self.secret_key,
+            chain_code: self.chain_code,
+        };
+        for index in parse_path(path)? {
+            key = key.derive_child(secp, index)?;
+        }
+        Ok(key)
+    }
+
+    fn derive_child(&self, secp: &Secp256k1<secp256k1::All>, index: u32) -> ZkpResult<Self> {
+        let index_bytes = index.to_be_bytes();
+        let (tweak, chain_code) = if index >= HARDENED {
+            hmac_sha512(&self.chain_code, &[&[0u8], &self.secret_key.secret_bytes(), &index_bytes])?
+        } else {
+            let public_key = PublicKey::from_secret_key(secp, &self.secret_key);
+            hmac_sha512(&self.chain_code, &[&public_key.serialize(), &index_bytes])?
+        };
+
+        let invalid = |e: secp256k1::Error| {
+            ZkpError::KeyGenerationError(format!("Invalid BIP-32 child key at index {}: {}", index, e))
+        };
+        let tweak = Scalar::from_be_bytes(tweak).map_err(|_| invalid(secp256k1::Error::InvalidTweak))?;
+        let secret_key = self.secret_key.add_tweak(&tweak).map_err(invalid)?;
+        Ok(Self { secret_key, chain_code })
+    }
+}
+
+/// Parse a BIP-32 path such as "m/44'/60'/0'/0/0" ("h" is accepted as a hardened marker too)
+pub fn parse_path(path: &str) -> ZkpResult<Vec<u32>> {
+    let invalid = || ZkpError::InvalidInput(format!("Invalid derivation path: {}", path));
+    let mut segments = path.trim().split('/');
+    if segments.next() != Some("m") {
+        return Err(invalid());
+    }
+
+    segments
+        .map(|segment| {
+            let (number, hardened) = match segment.strip_suffix('\'').or_else(|| segment.strip_suffix('h')) {
+                Some(number) => (number, true),
+                None => (segment, false),
+            };
+            let index: u32 = number.parse().map_err(|_| invalid())?;
+            if index >= HARDENED {
+                return Err(invalid());
+            }
+            Ok(if hardened { index | HARDENED } else { index })
+        })
+        .collect()
+}
+
+/// Stable BIP-44 style path for a tenant: m/44'/60'/a'/b'/c', where a, b and c are
+/// 31-bit slices of keccak256(tenant_id). Using three hardened levels keeps the chance of two
+/// tenants sharing a key negligible and prevents one tenant's key from revealing another's.
+pub fn tenant_path(tenant_id: &str) -> String {
+    let hash = ethereum::keccak256(tenant_id.as_bytes());
+    let slice = |i: usize| u32::from_be_bytes([hash[i], hash[i + 1], hash[i + 2], hash[i + 3]]) & !HARDENED;
+    format!("{}/{}'/{}'/{}'", TENANT_PATH_PREFIX, slice(0), slice(4), slice(8))
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> ZkpResult<([u8; 32], [u8; 32])> {
+    let mut mac = HmacSha512::new_from_slice(key)
+        .map_err(|e| ZkpError::KeyGenerationError(format!("HMAC initialisation failed: {}", e)))?;
+    for chunk in data {
+        mac.update(chunk);
+    }
+    let output = mac.finalize().into_bytes();
+    let mut left = [0u8; 32];
+    let mut right = [0u8; 32];
+    left.copy_from_slice(&output[..32]);
+    right.copy_from_slice(&output[32..]);
+    Ok((left, right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    // Chain code and private key from a base58check-encoded xprv
+    fn decode_xprv(xprv: &str) -> ([u8; 32], [u8; 32]) {
+        const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+        let mut bytes = [0u8; 82];
+        for c in xprv.bytes() {
+            let mut carry = ALPHABET.iter().position(|a| *a == c).unwrap() as u32;
+            for byte in bytes.iter_mut().rev() {
+                carry += *byte as u32 * 58;
+                *byte = carry as u8;
+                carry >>= 8;
+            }
+            assert_eq!(carry, 0);
+        }
+        let checksum = Sha256::digest(Sha256::digest(&bytes[..78]));
+        assert_eq!(&checksum[..4], &bytes[78..]);
+        (bytes[13..45].try_into().unwrap(), bytes[46..78].try_into().unwrap())
+    }
+
+    // BIP-32 test vector 1
+    #[test]
+    fn test_vector_1() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedKey::master(&seed).unwrap();
+        let secp = Secp256k1::new();
+
+        let chain = [
+            ("m", "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi"),
+            ("m/0'", "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7"),
+            ("m/0'/1", "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs"),
+            ("m/0'/1/2'", "xprv9z4pot5VBttmtdRTWfWQmoH1taj2axGVzFqSb8C9xaxKymcFzXBDptWmT7FwuEzG3ryjH4ktypQSAewRiNMjANTtpgP4mLTj34bhnZX7UiM"),
+            ("m/0'/1/2'/2", "xprvA2JDeKCSNNZky6uBCviVfJSKyQ1mDYahRjijr5idH2WwLsEd4Hsb2Tyh8RfQMuPh7f7RtyzTtdrbdqqsunu5Mm3wDvUAKRHSC34sJ7in334"),
+            ("m/0'/1/2'/2/1000000000", "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76"),
+        ];
+        for (path, xprv) in chain {
+            let key = master.derive_path(&secp, path).unwrap();
+            let (chain_code, secret_key) = decode_xprv(xprv);
+            assert_eq!(key.chain_code, chain_code, "chain code at {}", path);
+            assert_eq!(key.secret_key.secret_bytes(), secret_key, "key at {}", path);
+        }
+    }
+
+    #[test]
+    fn tenant_paths_are_hardened_below_the_prefix() {
+        let path = tenant_path("tenant-a");
+        assert!(path.starts_with("m/44'/60'/"));
+        let indices = parse_path(&path).unwrap();
+        assert_eq!(indices.len(), 5);
+        assert!(indices.iter().all(|index| *index >= HARDENED));
+        assert_ne!(path, tenant_path("tenant-b"));
+    }
+}
@@ -0,0 +1,350 @@
+//! Versioned service keys.
+//!
+//! The active key lives in the keystore file. Public information about every key version,
+//! together with the signed hand-over statements between them, is kept in a
+//! `key-history.json` file next to the keystore. Retired keystores are archived as
+//! `<keystore>.v<N>.json`; they stay loaded so envelopes sealed to them can still be opened.
+
+use crate::errors::{ZkpError, ZkpResult};
+use crate::ethereum;
+use crate::keystore;
+use secp256k1::{PublicKey, Secp256k1, SecretKey as SecpSecretKey};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const HISTORY_FILE_NAME: &str = "key-history.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyVersionInfo {
+    pub version: u32,
+    pub public_key: String,
+    pub status: String,
+    pub created_at: String,
+    pub retired_at: Option<String>,
+}
+
+/// Statement covered by both signatures of a key transition.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyTransitionStatement {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub from_version: u32,
+    pub from_public_key: String,
+    pub to_version: u32,
+    pub to_public_key: String,
+    pub timestamp: String,
+}
+
+/// A key hand-over. `statement` is the exact JSON string that both keys signed with EIP-191.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyTransition {
+    pub statement: String,
+    pub old_key_signature: String,
+    pub new_key_signature: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyHistory {
+    pub keys: Vec<KeyVersionInfo>,
+    pub transitions: Vec<KeyTransition>,
+}
+
+pub struct KeyRing {
+    active: SecpSecretKey,
+    retired: BTreeMap<u32, SecpSecretKey>,
+    history: KeyHistory,
+    keystore_path: PathBuf,
+    history_path: PathBuf,
+    passphrase: String,
+}
+
+impl KeyRing {
+    /// Open the key ring backed by `keystore_path`, creating the first key version if needed.
+    pub fn open(keystore_path: &Path, passphrase: &str) -> ZkpResult<Self> {
+        let history_path = keystore_path.with_file_name(HISTORY_FILE_NAME);
+        let secp = Secp256k1::signing_only();
+
+        let history = match std::fs::read(&history_path) {
+            Ok(contents) => Some(serde_json::from_slice::<KeyHistory>(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        // Once a history exists the keystore must exist too; never mint a replacement key
+        let mut active = if history.is_some() {
+            keystore::load(keystore_path, passphrase)?
+        } else {
+            keystore::load_or_create(keystore_path, passphrase)?
+        };
+        let active_public_key = ethereum::public_key_hex(&PublicKey::from_secret_key(&secp, &active));
+
+        let history = match history {
+            Some(history) => {
+                let expected = history
+                    .keys
+                    .last()
+                    .ok_or_else(|| ZkpError::KeystoreError("Key history is empty".to_string()))?;
+
+                if expected.public_key != active_public_key {
+                    // A rotation may have been interrupted after the history was written but
+                    // before the new keystore replaced the old one
+                    let pending_path = pending_path(keystore_path);
+                    let pending = keystore::load(&pending_path, passphrase).map_err(|_| {
+                        ZkpError::KeystoreError(format!(
+                            "Keystore key does not match active key version {} in {}",
+                            expected.version,
+                            history_path.display()
+                        ))
+                    })?;
+                    let pending_public_key =
+                        ethereum::public_key_hex(&PublicKey::from_secret_key(&secp, &pending));
+                    if pending_public_key != expected.public_key {
+                        return Err(ZkpError::KeystoreError(format!(
+                            "Keystore key does not match active key version {} in {}",
+                            expected.version,
+                            history_path.display()
+                        )));
+                    }
+                    std::fs::rename(&pending_path, keystore_path)?;
+                    active = pending;
+                }
+                history
+            }
+            None => {
+                let history = KeyHistory {
+                    keys: vec![KeyVersionInfo {
+                        version: 1,
+                        public_key: active_public_key,
+                        status: "active".to_string(),
+                        created_at: chrono::Utc::now().to_rfc3339(),
+                        retired_at: None,
+                    }],
+                    transitions: Vec::new(),
+                };
+                write_history(&history_path, &history)?;
+                history
+            }
+        };
+
+        let retired = load_retired(keystore_path, passphrase, &history)?;
+        Ok(Self {
+            active,
+            retired,
+            history,
+            keystore_path: keystore_path.to_path_buf(),
+            history_path,
+            passphrase: passphrase.to_string(),
+        })
+    }
+
+    pub fn active_key(&self) -> &SecpSecretKey {
+        &self.active
+    }
+
+    pub fn active_version(&self) -> u32 {
+        self.history.keys.last().map(|k| k.version).unwrap_or(1)
+    }
+
+    pub fn history(&self) -> &KeyHistory {
+        &self.history
+    }
+
+    /// The key of any version, active or retired
+    pub fn key(&self, version: u32) -> ZkpResult<&SecpSecretKey> {
+        if version == self.active_version() {
+            return Ok(&self.active);
+        }
+        self.retired
+            .get(&version)
+            .ok_or_else(|| ZkpError::KeystoreError(format!("Key version {} is not available", version)))
+    }
+
+    /// Replace the active key with a freshly generated one in a single step
+    #[cfg(test)]
+    pub fn rotate(&mut self) -> ZkpResult<KeyTransition> {
+        let rotation = self.stage_rotation()?;
+        rotation.persist()?;
+        self.commit_rotation(rotation)
+    }
+
+    /// Generate the next key and sign the hand-over to it, without touching the disk.
+    ///
+    /// The transition is signed by both the outgoing and the incoming key, so anyone trusting
+    /// the old key can follow the hand-over to the new one. The slow part,
+    /// `StagedRotation::persist`, needs no access to the key ring, so callers holding the ring
+    /// behind a lock only lock it for this and for `commit_rotation`.
+    pub fn stage_rotation(&self) -> ZkpResult<StagedRotation> {
+        let secp = Secp256k1::signing_only();
+        let new_key = keystore::generate_secret_key()?;
+        let now = chrono::Utc::now().to_rfc3339();
+
+        let from_version = self.active_version();
+        let to_version = from_version + 1;
+        let statement = KeyTransitionStatement {
+            kind: "key_transition".to_string(),
+            from_version,
+            from_public_key: ethereum::public_key_hex(&PublicKey::from_secret_key(&secp, &self.active)),
+            to_version,
+            to_public_key: ethereum::public_key_hex(&PublicKey::from_secret_key(&secp, &new_key)),
+            timestamp: now.clone(),
+        };
+        let statement = serde_json::to_string(&statement)?;
+        let transition = KeyTransition {
+            old_key_signature: ethereum::sign_personal_message(&secp, &self.active, statement.as_bytes())?,
+            new_key_signature: ethereum::sign_personal_message(&secp, &new_key, statement.as_bytes())?,
+            statement,
+        };
+
+        let mut history = self.history.clone();
+        if let Some(current) = history.keys.last_mut() {
+            current.status = "retired".to_string();
+            current.retired_at = Some(now.clone());
+        }
+        history.keys.push(KeyVersionInfo {
+            version: to_version,
+            public_key: ethereum::public_key_hex(&PublicKey::from_secret_key(&secp, &new_key)),
+            status: "active".to_string(),
+            created_at: now,
+            retired_at: None,
+        });
+        history.transitions.push(transition.clone());
+
+        Ok(StagedRotation {
+            new_key,
+            from_version,
+            history,
+            transition,
+            keystore_path: self.keystore_path.clone(),
+            history_path: self.history_path.clone(),
+            passphrase: self.passphrase.clone(),
+        })
+    }
+
+    /// Make a persisted rotation the active key in memory
+    pub fn commit_rotation(&mut self, rotation: StagedRotation) -> ZkpResult<KeyTransition> {
+        if rotation.from_version != self.active_version() {
+            return Err(ZkpError::KeystoreError(format!(
+                "Rotation from key version {} is stale; version {} is active",
+                rotation.from_version,
+                self.active_version()
+            )));
+        }
+        let retired = std::mem::replace(&mut self.active, rotation.new_key);
+        self.retired.insert(rotation.from_version, retired);
+        self.history = rotation.history;
+        Ok(rotation.transition)
+    }
+}
+
+/// A key rotation that has been generated and signed but not yet written or activated
+pub struct StagedRotation {
+    new_key: SecpSecretKey,
+    from_version: u32,
+    history: KeyHistory,
+    transition: KeyTransition,
+    keystore_path: PathBuf,
+    history_path: PathBuf,
+    passphrase: String,
+}
+
+impl StagedRotation {
+    /// Archive the outgoing keystore, stage the new one, then commit the history before
+    /// swapping the staged keystore into place. `KeyRing::open` finishes an interrupted swap.
+    pub fn persist(&self) -> ZkpResult<()> {
+        let outgoing = std::fs::read(&self.keystore_path)?;
+        keystore::write_private_file(&archive_path(&self.keystore_path, self.from_version), &outgoing)?;
+        let pending_path = pending_path(&self.keystore_path);
+        keystore::save(&pending_path, &self.new_key, &self.passphrase)?;
+        write_history(&self.history_path, &self.history)?;
+        std::fs::rename(&pending_path, &self.keystore_path)?;
+        Ok(())
+    }
+}
+
+// Retired keys from their archives. An archive that is gone only costs the ability to open
+// envelopes sealed to that version.
+fn load_retired(keystore_path: &Path, passphrase: &str, history: &KeyHistory) -> ZkpResult<BTreeMap<u32, SecpSecretKey>> {
+    let mut retired = BTreeMap::new();
+    let Some((_, older)) = history.keys.split_last() else {
+        return Ok(retired);
+    };
+    for info in older {
+        let archive = archive_path(keystore_path, info.version);
+        if !archive.try_exists()? {
+            eprintln!("Archive {} of retired key version {} is missing", archive.display(), info.version);
+            continue;
+        }
+        retired.insert(info.version, keystore::load(&archive, passphrase)?);
+    }
+    Ok(retired)
+}
+
+/// Write the key history for a keystore that is being restored from a backup
+pub fn restore_history(keystore_path: &Path, history: &KeyHistory) -> ZkpResult<()> {
+    write_history(&keystore_path.with_file_name(HISTORY_FILE_NAME), history)
+}
+
+fn pending_path(keystore_path: &Path) -> PathBuf {
+    keystore_path.with_extension("pending.json")
+}
+
+pub fn archive_path(keystore_path: &Path, version: u32) -> PathBuf {
+    keystore_path.with_extension(format!("v{}.json", version))
+}
+
+fn write_history(path: &Path, history: &KeyHistory) -> ZkpResult<()> {
+    keystore::write_private_file(path, &serde_json::to_vec_pretty(history)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::{EncryptionSuite, PROOF_INPUT_CONTEXT};
+    use crate::keystore::tests::TempDir;
+
+    #[test]
+    fn retired_keys_still_decrypt() {
+        let dir = TempDir::new();
+        let keystore_path = dir.0.join("service-key.json");
+        let mut keyring = KeyRing::open(&keystore_path, "passphrase").unwrap();
+        let secp = Secp256k1::new();
+        let old_public_key = PublicKey::from_secret_key(&secp, keyring.active_key());
+        let envelope = crate::ecies::encrypt(&old_public_key, b"{}", PROOF_INPUT_CONTEXT).unwrap();
+
+        keyring.rotate().unwrap();
+        assert_eq!(keyring.active_version(), 2);
+        let suite = EncryptionSuite::Secp256k1Ecies;
+        assert!(suite.decrypt(keyring.active_key(), &envelope, PROOF_INPUT_CONTEXT).is_err());
+        assert_eq!(suite.decrypt(keyring.key(1).unwrap(), &envelope, PROOF_INPUT_CONTEXT).unwrap(), b"{}");
+
+        // Retired keys are loaded again from their archives
+        let reopened = KeyRing::open(&keystore_path, "passphrase").unwrap();
+        assert_eq!(reopened.key(1).unwrap(), keyring.key(1).unwrap());
+        assert_eq!(reopened.key(2).unwrap(), keyring.active_key());
+        assert!(reopened.key(3).is_err());
+    }
+
+    #[test]
+    fn transition_is_signed_by_both_keys() {
+        let dir = TempDir::new();
+        let mut keyring = KeyRing::open(&dir.0.join("service-key.json"), "passphrase").unwrap();
+        let secp = Secp256k1::new();
+        let from = PublicKey::from_secret_key(&secp, keyring.active_key());
+
+        let transition = keyring.rotate().unwrap();
+        let to = PublicKey::from_secret_key(&secp, keyring.active_key());
+        let statement: KeyTransitionStatement = serde_json::from_str(&transition.statement).unwrap();
+        assert_eq!(statement.from_public_key, ethereum::public_key_hex(&from));
+        assert_eq!(statement.to_public_key, ethereum::public_key_hex(&to));
+
+        let digest = ethereum::personal_message_hash(transition.statement.as_bytes());
+        let recover = |signature: &str| {
+            let signature = ethereum::decode_hex_bytes(signature).unwrap();
+            ethereum::recover_public_key(&secp, &digest, &signature).unwrap()
+        };
+        assert_eq!(recover(&transition.old_key_signature), from);
+        assert_eq!(recover(&transition.new_key_signature), to);
+        assert_eq!(keyring.history().transitions.len(), 1);
+    }
+}
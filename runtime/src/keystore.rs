@@ -0,0 +1,442 @@
+//! Web3 Secret Storage (v3) keystore for the service signing key and other service secrets.
+//!
+//! Keys are written with scrypt + AES-128-CTR, the same layout geth and ethers use.
+//! Both scrypt and pbkdf2 keystores can be read back, including scrypt parameters outside
+//! RFC 7914's bounds that geth accepts.
+
+use crate::errors::{ZkpError, ZkpResult};
+use crate::ethereum;
+use aes::cipher::{KeyIvInit, StreamCipher};
+use rand::RngCore;
+use secp256k1::{PublicKey, Secp256k1, SecretKey as SecpSecretKey};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use std::io::Write;
+use std::path::Path;
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+// Same defaults as geth's "standard" scrypt parameters
+const SCRYPT_LOG_N: u8 = 18;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+const DKLEN: usize = 32;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeystoreFile {
+    pub version: u32,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(alias = "Crypto")]
+    pub crypto: CryptoSection,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CryptoSection {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    pub ciphertext: String,
+    pub kdf: String,
+    pub kdfparams: serde_json::Value,
+    pub mac: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub iv: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ScryptParams {
+    dklen: usize,
+    n: u64,
+    r: u32,
+    p: u32,
+    salt: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Pbkdf2Params {
+    dklen: usize,
+    c: u32,
+    prf: String,
+    salt: String,
+}
+
+/// Load the signing key from `path`, or create and persist a new one if the file does not exist.
+///
+/// Any other failure (unreadable file, wrong passphrase, corrupt JSON) is returned as an error
+/// so that a misconfigured deployment never silently replaces its identity.
+pub fn load_or_create(path: &Path, passphrase: &str) -> ZkpResult<SecpSecretKey> {
+    if !path.try_exists()? {
+        let secret_key = generate_secret_key()?;
+        save(path, &secret_key, passphrase)?;
+        return Ok(secret_key);
+    }
+    load(path, passphrase)
+}
+
+/// Load and decrypt an existing keystore file.
+pub fn load(path: &Path, passphrase: &str) -> ZkpResult<SecpSecretKey> {
+    let secret = load_secret(path, passphrase)?;
+    SecpSecretKey::from_slice(&secret)
+        .map_err(|e| ZkpError::KeystoreError(format!("Keystore does not hold a valid secp256k1 key: {}", e)))
+}
+
+/// Encrypt `secret_key` with `passphrase` and atomically write it to `path`.
+pub fn save(path: &Path, secret_key: &SecpSecretKey, passphrase: &str) -> ZkpResult<()> {
+    let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), secret_key);
+    save_secret(path, &secret_key.secret_bytes(), passphrase, Some(address_hex(&public_key)))
+}
+
+/// Load a `len`-byte secret that is not a signing key (e.g. a seed), creating it if the file is missing.
+pub fn load_or_create_secret(path: &Path, passphrase: &str, len: usize) -> ZkpResult<Vec<u8>> {
+    if !path.try_exists()? {
+        let mut secret = vec![0u8; len];
+        rand::thread_rng().fill_bytes(&mut secret);
+        save_secret(path, &secret, passphrase, None)?;
+        return Ok(secret);
+    }
+    load_sized_secret(path, passphrase, len)
+}
+
+/// Load an existing `len`-byte secret that is not a signing key
+pub fn load_sized_secret(path: &Path, passphrase: &str, len: usize) -> ZkpResult<Vec<u8>> {
+    let secret = load_secret(path, passphrase)?;
+    if secret.len() != len {
+        return Err(ZkpError::KeystoreError(format!(
+            "Keystore {} holds {} bytes, expected {}",
+            path.display(),
+            secret.len(),
+            len
+        )));
+    }
+    Ok(secret)
+}
+
+fn load_secret(path: &Path, passphrase: &str) -> ZkpResult<Vec<u8>> {
+    let contents = std::fs::read(path)
+        .map_err(|e| ZkpError::KeystoreError(format!("Failed to read keystore {}: {}", path.display(), e)))?;
+    let keystore: KeystoreFile = serde_json::from_slice(&contents)
+        .map_err(|e| ZkpError::KeystoreError(format!("Invalid keystore {}: {}", path.display(), e)))?;
+    decrypt(&keystore, passphrase)
+}
+
+/// Encrypt a secret that is not a signing key (e.g. a seed) and atomically write it to `path`
+pub fn save_secret(path: &Path, secret: &[u8], passphrase: &str, address: Option<String>) -> ZkpResult<()> {
+    let keystore = encrypt(secret, passphrase, address)?;
+    write_private_file(path, &serde_json::to_vec_pretty(&keystore)?)
+}
+
+/// Atomically replace `path` with `contents`, readable only by the service user
+pub fn write_private_file(path: &Path, contents: &[u8]) -> ZkpResult<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let tmp_path = path.with_extension("json.tmp");
+    {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
+
+pub fn encrypt(secret: &[u8], passphrase: &str, address: Option<String>) -> ZkpResult<KeystoreFile> {
+    let mut rng = rand::thread_rng();
+    let mut salt = [0u8; 32];
+    let mut iv = [0u8; 16];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut iv);
+
+    let params = scrypt::Params::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P, DKLEN)
+        .map_err(|e| ZkpError::KeystoreError(format!("Invalid scrypt parameters: {}", e)))?;
+    let mut derived_key = [0u8; DKLEN];
+    scrypt::scrypt(passphrase.as_bytes(), &salt, &params, &mut derived_key)
+        .map_err(|e| ZkpError::KeystoreError(format!("Key derivation failed: {}", e)))?;
+
+    let mut ciphertext = secret.to_vec();
+    Aes128Ctr::new(derived_key[..16].into(), &iv.into()).apply_keystream(&mut ciphertext);
+    let mac = compute_mac(&derived_key, &ciphertext);
+
+    let kdfparams = ScryptParams {
+        dklen: DKLEN,
+        n: 1u64 << SCRYPT_LOG_N,
+        r: SCRYPT_R,
+        p: SCRYPT_P,
+        salt: hex::encode(salt),
+    };
+
+    Ok(KeystoreFile {
+        version: 3,
+        id: uuid::Uuid::new_v4().to_string(),
+        address,
+        crypto: CryptoSection {
+            cipher: "aes-128-ctr".to_string(),
+            cipherparams: CipherParams { iv: hex::encode(iv) },
+            ciphertext: hex::encode(&ciphertext),
+            kdf: "scrypt".to_string(),
+            kdfparams: serde_json::to_value(kdfparams)?,
+            mac: hex::encode(mac),
+        },
+    })
+}
+
+pub fn decrypt(keystore: &KeystoreFile, passphrase: &str) -> ZkpResult<Vec<u8>> {
+    if keystore.version != 3 {
+        return Err(ZkpError::KeystoreError(format!(
+            "Unsupported keystore version: {}",
+            keystore.version
+        )));
+    }
+
+    let crypto = &keystore.crypto;
+    if crypto.cipher != "aes-128-ctr" {
+        return Err(ZkpError::KeystoreError(format!("Unsupported cipher: {}", crypto.cipher)));
+    }
+
+    let derived_key = derive_key(&crypto.kdf, &crypto.kdfparams, passphrase)?;
+    let ciphertext = decode_hex(&crypto.ciphertext, "ciphertext")?;
+    let expected_mac = decode_hex(&crypto.mac, "mac")?;
+
+    if !constant_time_eq(&compute_mac(&derived_key, &ciphertext), &expected_mac) {
+        return Err(ZkpError::KeystoreError(
+            "MAC mismatch: wrong passphrase or corrupted keystore".to_string(),
+        ));
+    }
+
+    let iv: [u8; 16] = decode_hex(&crypto.cipherparams.iv, "iv")?
+        .try_into()
+        .map_err(|_| ZkpError::KeystoreError("IV must be 16 bytes".to_string()))?;
+
+    let mut plaintext = ciphertext;
+    Aes128Ctr::new(derived_key[..16].into(), &iv.into()).apply_keystream(&mut plaintext);
+    Ok(plaintext)
+}
+
+fn derive_key(kdf: &str, kdfparams: &serde_json::Value, passphrase: &str) -> ZkpResult<Vec<u8>> {
+    match kdf {
+        "scrypt" => {
+            let params: ScryptParams = serde_json::from_value(kdfparams.clone())?;
+            if params.dklen < 32 || !params.n.is_power_of_two() || params.n < 2 {
+                return Err(ZkpError::KeystoreError("Invalid scrypt parameters".to_string()));
+            }
+            let log_n = params.n.trailing_zeros() as u8;
+            let salt = decode_hex(&params.salt, "salt")?;
+            let mut derived_key = vec![0u8; params.dklen];
+            match scrypt::Params::new(log_n, params.r, params.p, params.dklen) {
+                Ok(scrypt_params) => scrypt::scrypt(passphrase.as_bytes(), &salt, &scrypt_params, &mut derived_key)
+                    .map_err(|e| ZkpError::KeystoreError(format!("Key derivation failed: {}", e)))?,
+                // The scrypt crate enforces RFC 7914's N < 2^(16r), which geth never has, so
+                // keystores like the spec's own (N=2^18, r=1) are derived here instead
+                Err(_) => {
+                    scrypt_unbounded(passphrase.as_bytes(), &salt, params.n, params.r, params.p, &mut derived_key)?
+                }
+            }
+            Ok(derived_key)
+        }
+        "pbkdf2" => {
+            let params: Pbkdf2Params = serde_json::from_value(kdfparams.clone())?;
+            if params.prf != "hmac-sha256" {
+                return Err(ZkpError::KeystoreError(format!("Unsupported pbkdf2 prf: {}", params.prf)));
+            }
+            if params.dklen < 32 {
+                return Err(ZkpError::KeystoreError("Invalid pbkdf2 parameters".to_string()));
+            }
+            let salt = decode_hex(&params.salt, "salt")?;
+            let mut derived_key = vec![0u8; params.dklen];
+            pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.as_bytes(), &salt, params.c, &mut derived_key);
+            Ok(derived_key)
+        }
+        other => Err(ZkpError::KeystoreError(format!("Unsupported kdf: {}", other))),
+    }
+}
+
+// Upper bound on the scrypt working memory (128·r·N bytes) a keystore may ask for
+const SCRYPT_MAX_MEMORY: u64 = 1 << 30;
+
+// scrypt (RFC 7914) without the N < 2^(16r) bound, for keystores geth reads but the scrypt crate
+// refuses. `n` is a power of two.
+fn scrypt_unbounded(password: &[u8], salt: &[u8], n: u64, r: u32, p: u32, output: &mut [u8]) -> ZkpResult<()> {
+    let r128 = 128 * r as usize;
+    if r == 0 || p == 0 || (r as u64) * (p as u64) >= 1 << 30 || 128 * (r as u64) * n > SCRYPT_MAX_MEMORY {
+        return Err(ZkpError::KeystoreError("Invalid scrypt parameters".to_string()));
+    }
+    let n = n as usize;
+    // Blocks are handled as little-endian 32-bit words
+    let words = r128 / 4;
+
+    let mut b = vec![0u8; p as usize * r128];
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(password, salt, 1, &mut b);
+    let mut v = vec![0u32; n * words];
+    let mut x = vec![0u32; words];
+    let mut t = vec![0u32; words];
+    for block in b.chunks_mut(r128) {
+        for (word, bytes) in x.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        // ROMix
+        for i in 0..n {
+            v[i * words..(i + 1) * words].copy_from_slice(&x);
+            scrypt_block_mix(&v[i * words..(i + 1) * words], &mut x);
+        }
+        for _ in 0..n {
+            let j = x[words - 16] as usize & (n - 1);
+            let vj = &v[j * words..(j + 1) * words];
+            for k in 0..words {
+                t[k] = x[k] ^ vj[k];
+            }
+            scrypt_block_mix(&t, &mut x);
+        }
+        for (bytes, word) in block.chunks_exact_mut(4).zip(&x) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+    }
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(password, &b, 1, output);
+    Ok(())
+}
+
+// BlockMix with Salsa20/8: even 16-word results go to the first half of `output`, odd ones to
+// the second
+fn scrypt_block_mix(input: &[u32], output: &mut [u32]) {
+    use salsa20::cipher::{consts::U4, StreamCipherCore};
+
+    let mut x = [0u32; 16];
+    x.copy_from_slice(&input[input.len() - 16..]);
+    let mut keystream = [0u8; 64];
+    for (i, chunk) in input.chunks_exact(16).enumerate() {
+        for k in 0..16 {
+            x[k] ^= chunk[k];
+        }
+        salsa20::SalsaCore::<U4>::from_raw_state(x).write_keystream_block((&mut keystream).into());
+        for k in 0..16 {
+            let b = &keystream[4 * k..4 * k + 4];
+            x[k] = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        }
+        let offset = (i / 2) * 16 + (i % 2) * input.len() / 2;
+        output[offset..offset + 16].copy_from_slice(&x);
+    }
+}
+
+pub fn generate_secret_key() -> ZkpResult<SecpSecretKey> {
+    let mut rng = rand::thread_rng();
+    let mut key_bytes = [0u8; 32];
+    rng.fill_bytes(&mut key_bytes);
+    SecpSecretKey::from_slice(&key_bytes)
+        .map_err(|e| ZkpError::KeyGenerationError(format!("Failed to generate secret key: {}", e)))
+}
+
+fn compute_mac(derived_key: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(&derived_key[16..32]);
+    hasher.update(ciphertext);
+    hasher.finalize().into()
+}
+
+fn address_hex(public_key: &PublicKey) -> String {
+    hex::encode(ethereum::address_from_public_key(public_key))
+}
+
+fn decode_hex(value: &str, field: &str) -> ZkpResult<Vec<u8>> {
+    hex::decode(value.trim_start_matches("0x"))
+        .map_err(|e| ZkpError::KeystoreError(format!("Invalid {} hex: {}", field, e)))
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Scratch directory removed when dropped
+    pub(crate) struct TempDir(pub std::path::PathBuf);
+
+    impl TempDir {
+        pub(crate) fn new() -> Self {
+            let path = std::env::temp_dir().join(format!("zkpruntime-test-{}", uuid::Uuid::new_v4().simple()));
+            std::fs::create_dir(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = TempDir::new();
+        let path = dir.0.join("key.json");
+        let secret_key = generate_secret_key().unwrap();
+        save(&path, &secret_key, "passphrase").unwrap();
+
+        assert_eq!(load(&path, "passphrase").unwrap(), secret_key);
+        assert!(load(&path, "wrong").is_err());
+    }
+
+    // Test vectors from the Web3 Secret Storage Definition; passphrase "testpassword"
+    const SPEC_PRIVATE_KEY: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+    const SPEC_PBKDF2: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+            "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf": "pbkdf2",
+            "kdfparams": {
+                "c": 262144,
+                "dklen": 32,
+                "prf": "hmac-sha256",
+                "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+    const SPEC_SCRYPT: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "83dbcc02d8ccb40e466191a123791e0e" },
+            "ciphertext": "d172bf743a674da9cdad04534d56926ef8358534d458fffccd4e6ad2fbde479c",
+            "kdf": "scrypt",
+            "kdfparams": {
+                "dklen": 32,
+                "n": 262144,
+                "p": 8,
+                "r": 1,
+                "salt": "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+            },
+            "mac": "2103ac29920d71da29f15d75b4a16dbe95cfd7ff8faea1056c33131d846e3097"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+
+    #[test]
+    fn decrypts_spec_pbkdf2_keystore() {
+        let keystore: KeystoreFile = serde_json::from_str(SPEC_PBKDF2).unwrap();
+        assert_eq!(hex::encode(decrypt(&keystore, "testpassword").unwrap()), SPEC_PRIVATE_KEY);
+        assert!(decrypt(&keystore, "wrong").is_err());
+    }
+
+    #[test]
+    fn decrypts_spec_scrypt_keystore() {
+        let keystore: KeystoreFile = serde_json::from_str(SPEC_SCRYPT).unwrap();
+        assert_eq!(hex::encode(decrypt(&keystore, "testpassword").unwrap()), SPEC_PRIVATE_KEY);
+    }
+}
@@ -1,31 +1,186 @@
+mod backup;
+mod config;
+mod ecies;
+mod eip712;
+mod encryption;
 mod errors;
+mod ethereum;
+mod hd;
+mod keyring;
+mod keystore;
+mod queue;
+mod sealed_box;
 mod service;
+mod session;
+mod shamir;
+mod task_store;
+mod transaction;
 mod types;
+mod webhook;
 
 use axum::{
-    extract::{Path, State},
-    http::{HeaderMap, StatusCode},
-    response::Json,
+    body::Body,
+    extract::{
+        ws::{Message, WebSocketUpgrade},
+        Path, Query, Request, State,
+    },
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
     routing::{delete, get, post},
     Router,
 };
+use futures_util::{Stream, StreamExt};
 use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 
+use config::ServiceConfig;
+use errors::ZkpError;
+use keyring::KeyTransition;
 use service::ZkpService;
 use types::{
-    ConsultXRequest, ConsultXResponse, DecryptInputRequest, DecryptInputResponse,
+    AttestationRequest, AttestationResponse, BatchProofRequest, BatchStatusResponse, BatchSubmitResponse, ConsultXRequest, ConsultXResponse, DecryptInputRequest, DecryptInputResponse,
     DeleteDirectoryRequest, ErrorResponse, GitCloneRequest, GitCloneResponse,
-    PaidResourceResponse, PaymentProof, PaymentRequiredResponse, PublicKeyResponse, 
-    ProofRequest, ProofResponse, QueryStateResponse,
-    SignMessageRequest, SignMessageResponse, SubmitXRequest, SubmitXResponse,
-    TrackedDirectoriesResponse, VerifyPaymentResponse, WriteStateRequest,
+    PaidResourceResponse, PaymentProof, PaymentRequiredResponse, PublicKeyQuery, 
+    ProofRequest, ProofResponse, QueryStateResponse, SessionEnvelope, SessionHandshakeRequest, SessionHandshakeResponse,
+    SignMessageRequest, SignMessageResponse, SignTransactionRequest, SignTransactionResponse, SignTypedDataRequest, SignTypedDataResponse, SubmitXRequest, SubmitXResponse,
+    TaskEventsQuery,
+    TrackedDirectoriesResponse, VerifyPaymentRequest, VerifyPaymentResponse, VerifyProofRequest, VerifyProofResponse, VerifySignatureRequest, VerifySignatureResponse,
+    WriteStateRequest,
 };
 
 // Shared application state
 #[derive(Clone)]
 struct AppState {
     service: Arc<ZkpService>,
+    admin_token: Option<String>,
+}
+
+// Admin endpoints are disabled unless ADMIN_TOKEN is configured
+fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let expected = state.admin_token.as_deref().ok_or_else(|| {
+        (StatusCode::FORBIDDEN, Json(ErrorResponse { error: "Admin API is disabled".to_string() }))
+    })?;
+    let provided = headers.get("x-admin-token").map(|v| v.as_bytes()).unwrap_or_default();
+    if !keystore::constant_time_eq(provided, expected.as_bytes()) {
+        return Err((StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: "Invalid admin token".to_string() })));
+    }
+    Ok(())
+}
+
+const SESSION_HEADER: &str = "x-session-id";
+// Bodiless requests carry their envelope in these headers instead
+const SESSION_SEQ_HEADER: &str = "x-session-seq";
+const SESSION_TAG_HEADER: &str = "x-session-tag";
+// Matches axum's default request body limit
+const SESSION_BODY_LIMIT: usize = 2 * 1024 * 1024;
+// Large enough for a finished batch of 1000 proofs
+const SESSION_RESPONSE_LIMIT: usize = 64 * 1024 * 1024;
+
+// Requests carrying x-session-id have their body decrypted before routing and their response
+// encrypted under the same session; other requests pass through untouched. Requests without a
+// body (GETs) send x-session-seq and x-session-tag instead of an envelope and only get their
+// response sealed. Streamed responses (Server-Sent Events, WebSocket upgrades) cannot be sealed
+// as a whole, so they are refused with 400 rather than sent in the clear.
+async fn session_channel(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(session_id) = request
+        .headers()
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+    else {
+        return next.run(request).await;
+    };
+
+    let (request, seq) = match open_session_request(&state, &session_id, request).await {
+        Ok(opened) => opened,
+        Err(e) => return e.into_response(),
+    };
+    let response = next.run(request).await;
+    match seal_session_response(&state, &session_id, seq, response).await {
+        Ok(response) => response,
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn open_session_request(
+    state: &AppState,
+    session_id: &str,
+    request: Request,
+) -> Result<(Request, u64), (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }));
+    let (mut parts, body) = request.into_parts();
+    let body = axum::body::to_bytes(body, SESSION_BODY_LIMIT).await
+        .map_err(|e| bad_request(format!("Failed to read session request: {}", e)))?;
+    let header = |name: &str| parts.headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let bodiless = body.is_empty();
+    let envelope = if bodiless {
+        let seq = header(SESSION_SEQ_HEADER)
+            .ok_or_else(|| bad_request(format!("Requests without a body need {}", SESSION_SEQ_HEADER)))?
+            .parse()
+            .map_err(|e| bad_request(format!("Invalid {}: {}", SESSION_SEQ_HEADER, e)))?;
+        let tag = header(SESSION_TAG_HEADER)
+            .ok_or_else(|| bad_request(format!("Requests without a body need {}", SESSION_TAG_HEADER)))?;
+        SessionEnvelope { seq, ciphertext: tag }
+    } else {
+        serde_json::from_slice(&body).map_err(|e| bad_request(format!("Invalid session envelope: {}", e)))?
+    };
+    let ciphertext = hex::decode(envelope.ciphertext.trim_start_matches("0x"))
+        .map_err(|e| bad_request(format!("Invalid session ciphertext: {}", e)))?;
+
+    let path = parts.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let plaintext = state.service.sessions
+        .decrypt_request(session_id, envelope.seq, parts.method.as_str(), path, &ciphertext)
+        .map_err(|e| (StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: e.to_string() })))?;
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    if !bodiless {
+        parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    }
+    Ok((Request::from_parts(parts, Body::from(plaintext)), envelope.seq))
+}
+
+async fn seal_session_response(
+    state: &AppState,
+    session_id: &str,
+    seq: u64,
+    response: Response,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let internal_error = |error: String| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error }));
+    let streaming = response.status() == StatusCode::SWITCHING_PROTOCOLS
+        || response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .is_some_and(|v| v.as_bytes().starts_with(b"text/event-stream"));
+    if streaming {
+        // Dropping the response drops the stream (or the pending upgrade) with it
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Streaming endpoints are not available over a session; connect without x-session-id"
+                    .to_string(),
+            }),
+        ));
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, SESSION_RESPONSE_LIMIT).await
+        .map_err(|e| internal_error(format!("Failed to read response: {}", e)))?;
+    let ciphertext = state.service.sessions
+        .encrypt_response(session_id, seq, parts.status.as_u16(), &body)
+        .map_err(|e| internal_error(e.to_string()))?;
+
+    let envelope = serde_json::to_vec(&SessionEnvelope {
+        seq,
+        ciphertext: format!("0x{}", hex::encode(ciphertext)),
+    })
+    .map_err(|e| internal_error(e.to_string()))?;
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(Response::from_parts(parts, Body::from(envelope)))
 }
 
 // API Handlers
@@ -44,18 +199,105 @@ async fn health_check(State(state): State<AppState>) -> Result<Json<serde_json::
     }
 }
 
-async fn get_public_key(State(state): State<AppState>) -> Result<Json<PublicKeyResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let public_key = state.service.get_public_key()
+async fn attestation(
+    State(state): State<AppState>,
+    Json(request): Json<AttestationRequest>,
+) -> Result<Json<AttestationResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let response = state.service.attest(&request.nonce).await
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })))?;
+    Ok(Json(response))
+}
+
+async fn session_handshake(
+    State(state): State<AppState>,
+    Json(request): Json<SessionHandshakeRequest>,
+) -> Result<Json<SessionHandshakeResponse>, Response> {
+    let response = state.service.open_session(&request.client_public_key).map_err(|e| match e {
+        ZkpError::SessionLimit { retry_after_secs } => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, retry_after_secs.to_string())],
+            Json(ErrorResponse { error: e.to_string() }),
+        )
+            .into_response(),
+        e => (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })).into_response(),
+    })?;
+    Ok(Json(response))
+}
+
+async fn get_public_key(
+    State(state): State<AppState>,
+    Query(query): Query<PublicKeyQuery>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    if let Some(tenant_id) = &query.tenant_id {
+        let response = state.service
+            .get_derived_public_key(tenant_id)
+            .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })))?;
+        return Ok(Json(response).into_response());
+    }
+
+    let response = state.service.get_key_info()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })))?;
+    Ok(Json(response).into_response())
+}
+
+async fn rotate_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<KeyTransition>, (StatusCode, Json<ErrorResponse>)> {
+    require_admin(&state, &headers)?;
+    // Rotation re-encrypts the keystore (scrypt), keep it off the async workers
+    let service = state.service.clone();
+    let transition = tokio::task::spawn_blocking(move || service.rotate_key())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })))?
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })))?;
-    Ok(Json(PublicKeyResponse { public_key }))
+    Ok(Json(transition))
+}
+
+// Backpressure: a full queue tells the client when a slot is likely to free up
+fn submission_error(e: ZkpError) -> Response {
+    match e {
+        ZkpError::QueueFull { retry_after_secs } => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after_secs.to_string())],
+            Json(ErrorResponse { error: e.to_string() }),
+        )
+            .into_response(),
+        e => (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })).into_response(),
+    }
 }
 
 async fn execute_zkp(
     State(state): State<AppState>,
     Json(request): Json<ProofRequest>,
-) -> Result<Json<ProofResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let response = state.service.execute_zkp(request).await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })))?;
+) -> Result<Json<ProofResponse>, Response> {
+    let response = state.service.execute_zkp(request).await.map_err(submission_error)?;
+    Ok(Json(response))
+}
+
+async fn execute_zkp_batch(
+    State(state): State<AppState>,
+    Json(request): Json<BatchProofRequest>,
+) -> Result<Json<BatchSubmitResponse>, Response> {
+    let response = state.service.execute_zkp_batch(request).await.map_err(submission_error)?;
+    Ok(Json(response))
+}
+
+async fn retrieve_batch(
+    State(state): State<AppState>,
+    Path(batch_id): Path<String>,
+) -> Result<Json<BatchStatusResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let response = state.service.retrieve_batch(&batch_id).await
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })))?;
+    Ok(Json(response))
+}
+
+async fn verify_proof(
+    State(state): State<AppState>,
+    Json(request): Json<VerifyProofRequest>,
+) -> Result<Json<VerifyProofResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let response = state.service.verify_proof(request).await
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })))?;
     Ok(Json(response))
 }
 
@@ -63,11 +305,52 @@ async fn retrieve_output(
     State(state): State<AppState>,
     Path(task_id): Path<String>,
 ) -> Result<Json<ProofResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let response = state.service.retrieve_output(&task_id)
+    let response = state.service.retrieve_output(&task_id).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })))?;
     Ok(Json(response))
 }
 
+async fn cancel_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<String>,
+) -> Result<Json<ProofResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let response = state.service.cancel_task(&task_id).await
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })))?;
+    Ok(Json(response))
+}
+
+// Server-Sent Events stream of task status changes, named after the task status
+async fn task_events(
+    State(state): State<AppState>,
+    Query(query): Query<TaskEventsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, (StatusCode, Json<ErrorResponse>)> {
+    let events = state.service.watch_tasks(&query.task_ids()).await
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })))?;
+    let events = events.map(|event| Event::default().event(event.task.status.clone()).json_data(&event));
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// Same events as /task-events, one JSON text message each; the server closes the socket once
+// every task has finished
+async fn task_events_ws(
+    State(state): State<AppState>,
+    Query(query): Query<TaskEventsQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let events = state.service.watch_tasks(&query.task_ids()).await
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })))?;
+    Ok(ws.on_upgrade(|mut socket| async move {
+        let mut events = std::pin::pin!(events);
+        while let Some(event) = events.next().await {
+            let Ok(text) = serde_json::to_string(&event) else { break };
+            if socket.send(Message::Text(text)).await.is_err() {
+                return;
+            }
+        }
+        let _ = socket.send(Message::Close(None)).await;
+    }))
+}
+
 async fn write_state(
     State(state): State<AppState>,
     Json(request): Json<WriteStateRequest>,
@@ -104,13 +387,17 @@ async fn submit_x(
     Ok(Json(SubmitXResponse { submission_id }))
 }
 
+// Returns plaintext for anything sealed to the service key, so only operators may use it
 async fn decrypt_input(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(request): Json<DecryptInputRequest>,
 ) -> Result<Json<DecryptInputResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let decrypted = state.service.decrypt_input(&request.encrypted_data)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })))?;
-    Ok(Json(DecryptInputResponse { decrypted }))
+    require_admin(&state, &headers)?;
+    let (decrypted, encoding) = state.service
+        .decrypt_input(&request.encrypted_data, request.suite.as_deref(), request.key_version, request.encoding.as_deref())
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })))?;
+    Ok(Json(DecryptInputResponse { decrypted, encoding }))
 }
 
 async fn git_clone(
@@ -147,15 +434,54 @@ async fn list_tracked_directories(
     Ok(Json(TrackedDirectoriesResponse { directories }))
 }
 
+// Tenants are authenticated by the operator's backend, which signs on their behalf with the
+// admin token; the service key itself may be used by anyone
 async fn sign_message(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(request): Json<SignMessageRequest>,
 ) -> Result<Json<SignMessageResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let signature = state.service.sign_message(&request.message)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })))?;
-    let public_key = state.service.get_public_key()
+    if request.tenant_id.is_some() {
+        require_admin(&state, &headers)?;
+    }
+    let response = state.service
+        .sign_message(&request.message, request.tenant_id.as_deref())
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e.to_string() })))?;
-    Ok(Json(SignMessageResponse { signature, public_key }))
+    Ok(Json(response))
+}
+
+// Typed data covers permits and marketplace orders for the service account, so only operators
+// may request it
+async fn sign_typed_data(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SignTypedDataRequest>,
+) -> Result<Json<SignTypedDataResponse>, (StatusCode, Json<ErrorResponse>)> {
+    require_admin(&state, &headers)?;
+    let response = state.service.sign_typed_data(&request.typed_data)
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })))?;
+    Ok(Json(response))
+}
+
+// Signs arbitrary transactions as the service account, so only operators may use it
+async fn sign_transaction(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SignTransactionRequest>,
+) -> Result<Json<SignTransactionResponse>, (StatusCode, Json<ErrorResponse>)> {
+    require_admin(&state, &headers)?;
+    let response = state.service.sign_transaction(&request.transaction)
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })))?;
+    Ok(Json(response))
+}
+
+async fn verify_signature(
+    State(state): State<AppState>,
+    Json(request): Json<VerifySignatureRequest>,
+) -> Result<Json<VerifySignatureResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let response = state.service.verify_signature(&request)
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e.to_string() })))?;
+    Ok(Json(response))
 }
 
 async fn paid_resource(
@@ -218,11 +544,11 @@ async fn paid_resource(
 
     // 3. Verify payment with facilitator
     // Note: serde will automatically convert snake_case to camelCase due to rename_all
-    let verify_request = serde_json::json!({
-        "paymentProof": payment_proof,
-        "requiredAmount": required_amount,
-        "requiredRecipient": merchant_address,
-    });
+    let verify_request = VerifyPaymentRequest {
+        payment_proof,
+        required_amount: required_amount.clone(),
+        required_recipient: merchant_address.clone(),
+    };
 
     let client = reqwest::Client::new();
     let verify_url = format!("{}/api/facilitator/verify", facilitator_url);
@@ -290,24 +616,86 @@ async fn paid_resource(
     }))
 }
 
+// Operator subcommands:
+//   zkpruntime split-key --threshold M --shares N
+//   zkpruntime restore-key [--public-key KEY] SHARE_FILE...
+fn run_command(command: &str, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let config = ServiceConfig::from_env()?;
+
+    let mut flags = std::collections::HashMap::new();
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg.starts_with("--") {
+            let value = iter.next().ok_or_else(|| format!("Missing value for {}", arg))?;
+            flags.insert(arg.as_str(), value.as_str());
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+
+    match command {
+        "split-key" => {
+            let threshold: u8 = flags.get("--threshold").ok_or("--threshold is required")?.parse()?;
+            let shares: u8 = flags.get("--shares").ok_or("--shares is required")?.parse()?;
+            for share in backup::split_keystore(&config, threshold, shares)? {
+                println!("{}", serde_json::to_string(&share)?);
+            }
+            Ok(())
+        }
+        "restore-key" => {
+            let mut shares = Vec::new();
+            for path in &positional {
+                shares.extend(backup::read_shares(std::path::Path::new(path))?);
+            }
+            let public_key = backup::restore_keystore(&config, &shares, flags.get("--public-key").copied())?;
+            println!("Restored key {} to {}", ethereum::public_key_hex(&public_key), config.keystore_path.display());
+            Ok(())
+        }
+        other => Err(format!("Unknown command: {}", other).into()),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mock_mode = std::env::var("MOCK_MODE").unwrap_or_else(|_| "false".to_string()) == "true";
-    let service = Arc::new(ZkpService::new(mock_mode)?);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        return run_command(command, &args[1..]);
+    }
+
+    let config = ServiceConfig::from_env()?;
+    let service = Arc::new(ZkpService::new(&config)?);
     
-    let public_key = service.get_public_key()?;
+    let key_info = service.get_key_info()?;
     println!("ZKP Service starting...");
-    println!("Public Key: {}", public_key);
-    println!("Mock Mode: {}", mock_mode);
+    println!("Keystore: {}", config.keystore_path.display());
+    println!("Public Key: {}", key_info.public_key);
+    println!("Address: {} (key version {})", key_info.address, key_info.key_version);
+    println!("Mock Mode: {}", config.mock_mode);
     
-    let app_state = AppState { service };
+    let app_state = AppState {
+        service,
+        admin_token: config.admin_token.clone(),
+    };
     
     let app = Router::new()
         .route("/health", get(health_check))
         .route("/public-key", get(get_public_key))
+        .route("/attestation", post(attestation))
+        .route("/session/handshake", post(session_handshake))
         .route("/sign-message", post(sign_message))
+        .route("/sign-typed-data", post(sign_typed_data))
+        .route("/sign-transaction", post(sign_transaction))
+        .route("/verify-signature", post(verify_signature))
+        .route("/admin/rotate-key", post(rotate_key))
         .route("/execute-zkp", post(execute_zkp))
         .route("/retrieve-output/:task_id", get(retrieve_output))
+        .route("/execute-zkp-batch", post(execute_zkp_batch))
+        .route("/verify-proof", post(verify_proof))
+        .route("/retrieve-batch/:batch_id", get(retrieve_batch))
+        .route("/cancel-task/:task_id", post(cancel_task))
+        .route("/task-events", get(task_events))
+        .route("/task-events/ws", get(task_events_ws))
         .route("/write-state", post(write_state))
         .route("/query-state/:key", get(query_state))
         .route("/consult-x", post(consult_x))
@@ -318,6 +706,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/directory", delete(delete_directory))
         .route("/tracked-directories", get(list_tracked_directories))
         .route("/api/paid/resource", post(paid_resource))
+        .layer(middleware::from_fn_with_state(app_state.clone(), session_channel))
         .layer(CorsLayer::permissive())
         .with_state(app_state);
     
@@ -325,15 +714,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Server listening on http://0.0.0.0:3000");
     println!("API Endpoints:");
     println!("   GET  /health");
-    println!("   GET  /public-key");
-    println!("   POST /sign-message");
+    println!("   GET  /public-key[?tenant_id=]");
+    println!("   POST /attestation");
+    println!("   POST /session/handshake (then send x-session-id to encrypt any route)");
+    println!("   POST /sign-message (admin with tenant_id)");
+    println!("   POST /sign-typed-data (admin)");
+    println!("   POST /sign-transaction (admin)");
+    println!("   POST /verify-signature");
+    println!("   POST /admin/rotate-key");
     println!("   POST /execute-zkp");
     println!("   GET  /retrieve-output/:task_id");
+    println!("   POST /execute-zkp-batch");
+    println!("   POST /verify-proof");
+    println!("   GET  /retrieve-batch/:batch_id");
+    println!("   POST /cancel-task/:task_id");
+    println!("   GET  /task-events?task_ids=a,b (Server-Sent Events)");
+    println!("   GET  /task-events/ws?task_ids=a,b (WebSocket)");
     println!("   POST /write-state");
     println!("   GET  /query-state/:key");
     println!("   POST /consult-x");
     println!("   POST /submit-x");
-    println!("   POST /decrypt-input");
+    println!("   POST /decrypt-input (admin)");
     println!("   POST /git-clone");
     println!("   DELETE /directory/:uuid");
     println!("   DELETE /directory");
@@ -0,0 +1,364 @@
+//! Bounded priority queue feeding the proof worker pool, with wait estimates from recent proving
+//! times.
+//!
+//! Tasks are taken highest priority first and in submission order within a priority. A waiting
+//! task is promoted one class for every `aging` it has spent queued, so low-priority work still
+//! runs while higher-priority work keeps arriving.
+//!
+//! The queue also tracks which tasks are being proved so they can be cancelled: each task handed
+//! to a worker comes with a receiver that fires when the task is cancelled.
+
+use crate::errors::{ZkpError, ZkpResult};
+use crate::types::{ProofPriority, QueuedProofTask};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Notify};
+
+// Number of recent proving times averaged for wait estimates
+const DURATION_SAMPLES: usize = 32;
+// Retry-After used before any task has finished
+const DEFAULT_RETRY_AFTER_SECS: u64 = 5;
+
+pub struct TaskQueue {
+    capacity: usize,
+    workers: usize,
+    aging: Duration,
+    state: Mutex<QueueState>,
+    available: Notify,
+    durations: Mutex<VecDeque<Duration>>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    // One FIFO lane per priority class, indexed by `ProofPriority::rank`
+    lanes: [VecDeque<PendingTask>; ProofPriority::ALL.len()],
+    next_sequence: u64,
+    // Slots held by reservations that have not been filled yet
+    reserved: usize,
+    running: HashMap<String, oneshot::Sender<()>>,
+}
+
+struct PendingTask {
+    task: QueuedProofTask,
+    sequence: u64,
+    enqueued_at: Instant,
+}
+
+impl QueueState {
+    fn len(&self) -> usize {
+        self.lanes.iter().map(VecDeque::len).sum()
+    }
+
+    fn push(&mut self, task: QueuedProofTask) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.lanes[task.priority.rank()].push_back(PendingTask {
+            task,
+            sequence,
+            enqueued_at: Instant::now(),
+        });
+    }
+
+    // Lane holding the next task to run: the best aged rank among lane heads, oldest first on ties.
+    // Heads are the oldest task of their lane, so they also carry the lane's best aged rank.
+    fn next_lane(&self, now: Instant, aging: Duration) -> Option<usize> {
+        self.lanes
+            .iter()
+            .enumerate()
+            .filter_map(|(lane, tasks)| tasks.front().map(|head| (head.order_key(lane, now, aging), lane)))
+            .min()
+            .map(|(_, lane)| lane)
+    }
+}
+
+impl PendingTask {
+    // Sort key: aged rank, then submission order
+    fn order_key(&self, lane: usize, now: Instant, aging: Duration) -> (u64, u64) {
+        let promotions = now.saturating_duration_since(self.enqueued_at).as_secs() / aging.as_secs().max(1);
+        ((lane as u64).saturating_sub(promotions), self.sequence)
+    }
+}
+
+/// Queue slots held for tasks that are being stored. Dropping an unfilled reservation
+/// releases its slots.
+pub struct Reservation<'a> {
+    queue: &'a TaskQueue,
+    count: usize,
+}
+
+impl Reservation<'_> {
+    /// Enqueue the tasks the slots were reserved for
+    pub fn push_all(mut self, tasks: Vec<QueuedProofTask>) {
+        debug_assert_eq!(tasks.len(), self.count);
+        {
+            let mut state = self.queue.state.lock().unwrap();
+            state.reserved -= self.count;
+            for task in tasks {
+                state.push(task);
+            }
+        }
+        for _ in 0..self.count {
+            self.queue.available.notify_one();
+        }
+        self.count = 0;
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if self.count > 0 {
+            self.queue.state.lock().unwrap().reserved -= self.count;
+        }
+    }
+}
+
+pub enum Cancellation {
+    /// The task was still queued and has been removed
+    Dequeued,
+    /// The task is being proved; its worker has been told to stop
+    Signalled,
+    /// The task is neither queued nor running
+    NotActive,
+}
+
+impl TaskQueue {
+    pub fn new(capacity: usize, workers: usize, aging: Duration) -> Self {
+        Self {
+            capacity,
+            workers: workers.max(1),
+            aging,
+            state: Mutex::new(QueueState::default()),
+            available: Notify::new(),
+            durations: Mutex::new(VecDeque::with_capacity(DURATION_SAMPLES)),
+        }
+    }
+
+    /// Hold `count` slots, all or none, so a task is only stored once it is sure to fit. Fails
+    /// with `QueueFull` unless every slot is free.
+    pub fn reserve(&self, count: usize) -> ZkpResult<Reservation<'_>> {
+        let mut state = self.state.lock().unwrap();
+        if state.len() + state.reserved + count > self.capacity {
+            return Err(ZkpError::QueueFull {
+                retry_after_secs: self.retry_after_secs(),
+            });
+        }
+        state.reserved += count;
+        Ok(Reservation { queue: self, count })
+    }
+
+    /// Enqueue a task recovered at startup. Work accepted before a restart is never dropped,
+    /// even if it exceeds the capacity.
+    pub fn requeue(&self, task: QueuedProofTask) {
+        self.state.lock().unwrap().push(task);
+        self.available.notify_one();
+    }
+
+    /// Wait for the next task and mark it running. The receiver fires if the task is cancelled;
+    /// call `finish` once the worker is done with it.
+    pub async fn pop(&self) -> (QueuedProofTask, oneshot::Receiver<()>) {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                let next = state
+                    .next_lane(Instant::now(), self.aging)
+                    .and_then(|lane| state.lanes[lane].pop_front());
+                if let Some(PendingTask { task, .. }) = next {
+                    let (cancel_sender, cancel_receiver) = oneshot::channel();
+                    state.running.insert(task.task_id.clone(), cancel_sender);
+                    return (task, cancel_receiver);
+                }
+            }
+            self.available.notified().await;
+        }
+    }
+
+    pub fn finish(&self, task_id: &str) {
+        self.state.lock().unwrap().running.remove(task_id);
+    }
+
+    pub fn cancel(&self, task_id: &str) -> Cancellation {
+        let mut state = self.state.lock().unwrap();
+        for lane in state.lanes.iter_mut() {
+            if let Some(index) = lane.iter().position(|pending| pending.task.task_id == task_id) {
+                lane.remove(index);
+                return Cancellation::Dequeued;
+            }
+        }
+        match state.running.remove(task_id) {
+            Some(cancel_sender) => {
+                let _ = cancel_sender.send(());
+                Cancellation::Signalled
+            }
+            None => Cancellation::NotActive,
+        }
+    }
+
+    /// 1-based position of a queued task, counting the tasks that would run before it if nothing
+    /// else were submitted
+    pub fn position(&self, task_id: &str) -> Option<usize> {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let keys: Vec<((u64, u64), &str)> = state
+            .lanes
+            .iter()
+            .enumerate()
+            .flat_map(|(lane, tasks)| {
+                tasks
+                    .iter()
+                    .map(move |pending| (pending.order_key(lane, now, self.aging), pending.task.task_id.as_str()))
+            })
+            .collect();
+        let (own_key, _) = keys.iter().find(|(_, id)| *id == task_id)?;
+        Some(keys.iter().filter(|(key, _)| key < own_key).count() + 1)
+    }
+
+    pub fn record_duration(&self, duration: Duration) {
+        let mut durations = self.durations.lock().unwrap();
+        if durations.len() == DURATION_SAMPLES {
+            durations.pop_front();
+        }
+        durations.push_back(duration);
+    }
+
+    /// Expected time until the task at `position` starts, assuming every worker proves at the
+    /// recent average rate. `None` until a task has finished.
+    pub fn estimated_wait(&self, position: usize) -> Option<Duration> {
+        let average = self.average_duration()?;
+        let rounds = position.div_ceil(self.workers) as u32;
+        Some(average * rounds)
+    }
+
+    fn average_duration(&self) -> Option<Duration> {
+        let durations = self.durations.lock().unwrap();
+        if durations.is_empty() {
+            return None;
+        }
+        Some(durations.iter().sum::<Duration>() / durations.len() as u32)
+    }
+
+    // One slot frees up roughly every average / workers
+    fn retry_after_secs(&self) -> u64 {
+        match self.average_duration() {
+            Some(average) => (average / self.workers as u32).as_secs().max(1),
+            None => DEFAULT_RETRY_AFTER_SECS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::EncryptionSuite;
+    use crate::types::TaskInput;
+
+    fn task(task_id: &str, priority: ProofPriority) -> QueuedProofTask {
+        QueuedProofTask {
+            task_id: task_id.to_string(),
+            circuit_path: "circuit".to_string(),
+            input: TaskInput {
+                suite: EncryptionSuite::Secp256k1Ecies,
+                key_version: None,
+                payload: Vec::new(),
+                sealed_salt: Vec::new(),
+            },
+            mock_mode: true,
+            recipient: None,
+            timeout_secs: None,
+            priority,
+        }
+    }
+
+    fn push(queue: &TaskQueue, task_id: &str, priority: ProofPriority) {
+        queue.reserve(1).unwrap().push_all(vec![task(task_id, priority)]);
+    }
+
+    #[test]
+    fn full_queue_rejects_reservations() {
+        let queue = TaskQueue::new(2, 1, Duration::from_secs(60));
+        push(&queue, "a", ProofPriority::Normal);
+        let held = queue.reserve(1).unwrap();
+        assert!(matches!(
+            queue.reserve(1),
+            Err(ZkpError::QueueFull {
+                retry_after_secs: DEFAULT_RETRY_AFTER_SECS
+            })
+        ));
+
+        // An unfilled reservation gives its slot back
+        drop(held);
+        push(&queue, "b", ProofPriority::Normal);
+        assert!(queue.reserve(1).is_err());
+
+        // Recovered tasks are never dropped, even past capacity
+        queue.requeue(task("c", ProofPriority::Normal));
+        assert_eq!(queue.position("c"), Some(3));
+    }
+
+    #[test]
+    fn batch_reservations_are_all_or_nothing() {
+        let queue = TaskQueue::new(3, 1, Duration::from_secs(60));
+        push(&queue, "a", ProofPriority::Normal);
+        assert!(queue.reserve(3).is_err());
+        let slots = queue.reserve(2).unwrap();
+        slots.push_all(vec![task("b", ProofPriority::Low), task("c", ProofPriority::Low)]);
+        assert_eq!(queue.position("c"), Some(3));
+        assert!(queue.reserve(1).is_err());
+    }
+
+    #[test]
+    fn position_follows_priority_then_submission_order() {
+        let queue = TaskQueue::new(10, 1, Duration::from_secs(60));
+        push(&queue, "low", ProofPriority::Low);
+        push(&queue, "normal", ProofPriority::Normal);
+        push(&queue, "high-1", ProofPriority::High);
+        push(&queue, "high-2", ProofPriority::High);
+
+        assert_eq!(queue.position("high-1"), Some(1));
+        assert_eq!(queue.position("high-2"), Some(2));
+        assert_eq!(queue.position("normal"), Some(3));
+        assert_eq!(queue.position("low"), Some(4));
+        assert_eq!(queue.position("missing"), None);
+
+        assert!(matches!(queue.cancel("high-1"), Cancellation::Dequeued));
+        assert_eq!(queue.position("low"), Some(3));
+    }
+
+    #[test]
+    fn wait_estimates_use_the_recent_average() {
+        let queue = TaskQueue::new(10, 2, Duration::from_secs(60));
+        assert_eq!(queue.estimated_wait(1), None);
+
+        queue.record_duration(Duration::from_secs(10));
+        queue.record_duration(Duration::from_secs(20));
+        // Two workers: positions 1 and 2 start after one round, 3 after two
+        assert_eq!(queue.estimated_wait(1), Some(Duration::from_secs(15)));
+        assert_eq!(queue.estimated_wait(2), Some(Duration::from_secs(15)));
+        assert_eq!(queue.estimated_wait(3), Some(Duration::from_secs(30)));
+        assert_eq!(queue.retry_after_secs(), 7);
+
+        // Only the latest samples count
+        for _ in 0..DURATION_SAMPLES {
+            queue.record_duration(Duration::from_secs(4));
+        }
+        assert_eq!(queue.estimated_wait(1), Some(Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn waiting_tasks_age_past_newer_high_priority_work() {
+        let aging = Duration::from_secs(30);
+        let queue = TaskQueue::new(10, 1, aging);
+        push(&queue, "low", ProofPriority::Low);
+        push(&queue, "high", ProofPriority::High);
+
+        let state = queue.state.lock().unwrap();
+        let now = Instant::now();
+        let high_lane = ProofPriority::High.rank();
+        let low_lane = ProofPriority::Low.rank();
+        assert_eq!(state.next_lane(now, aging), Some(high_lane));
+        // One promotion takes it to normal, still behind high
+        assert_eq!(state.next_lane(now + aging, aging), Some(high_lane));
+        // Two promotions tie it with high, and it was submitted first
+        assert_eq!(state.next_lane(now + aging * 2, aging), Some(low_lane));
+    }
+}
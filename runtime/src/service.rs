@@ -1,223 +1,1223 @@
 pub use crate::types::ZkpService;
 
+use crate::backup;
+use crate::config::{RetentionPolicy, ServiceConfig};
+use crate::ecies;
 use crate::errors::{ZkpError, ZkpResult};
-use crate::types::{ProofRequest, ProofResponse, ProofStatus, ProofTask, QueuedProofTask};
+use crate::eip712::TypedData;
+use crate::encryption::{EncryptionSuite, INPUT_SALT_CONTEXT, PROOF_INPUT_CONTEXT};
+use crate::ethereum;
+use crate::hd::{self, ExtendedKey};
+use crate::keyring::{KeyRing, KeyTransition};
+use crate::keystore;
+use crate::queue::{Cancellation, TaskQueue};
+use crate::session::SessionManager;
+use crate::task_store::{self, FileTaskStore, MemoryTaskStore, TaskStore};
+use crate::transaction::Eip1559Transaction;
+use crate::webhook::{self, WebhookSender};
+use crate::types::{
+    AttestationDocument, AttestationResponse, BatchProofRequest, BatchRef, BatchStatusResponse, BatchSubmitResponse,
+    DerivedPublicKeyResponse, EncryptionKeyInfo, RuntimeInfo,
+    ProofPhase, ProofReceipt, ProofRequest, ProofResponse, ProofStatus, ProofTask, PublicKeyEntry, PublicKeyResponse, QueuedProofTask, SealedProofOutput,
+    SessionHandshakeResponse, SignMessageResponse, SignTransactionResponse, SignTypedDataResponse, TaskEvent, TaskInput, VerifyProofRequest, VerifyProofResponse, VerifySignatureRequest, VerifySignatureResponse,
+};
+use futures_util::{stream, Stream};
 use rand::RngCore;
-use secp256k1::{Message, Secp256k1, SecretKey as SecpSecretKey};
-use sha3::{Digest, Keccak256};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use secp256k1::{Secp256k1, SecretKey as SecpSecretKey};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
-use tokio::sync::mpsc;
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
+const MAX_BATCH_SIZE: usize = 1000;
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(120);
+const SESSION_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+// Task events buffered per subscriber; a subscriber that falls further behind is resynced
+const TASK_EVENT_CAPACITY: usize = 1024;
+
 impl ZkpService {
-    pub fn new(mock_mode: bool) -> ZkpResult<Self> {
-        let secp = Secp256k1::new();
-        let mut rng = rand::thread_rng();
-        let mut key_bytes = [0u8; 32];
-        rng.fill_bytes(&mut key_bytes);
-        let secret_key = SecpSecretKey::from_slice(&key_bytes)
-            .map_err(|e| ZkpError::KeyGenerationError(format!("Failed to generate secret key: {}", e)))?;
+    pub fn new(config: &ServiceConfig) -> ZkpResult<Self> {
+        let mock_mode = config.mock_mode;
+        let secp = Arc::new(Secp256k1::new());
+        // A lost keystore can be rebuilt from Shamir shares before the key ring is opened. The
+        // operator must name the key to expect: the shares' own tag proves nothing about them.
+        if let Some(shares_path) = &config.key_restore_shares
+            && !config.keystore_path.try_exists()?
+        {
+            let expected_public_key = config.key_restore_public_key.as_deref().ok_or_else(|| {
+                ZkpError::KeystoreError(
+                    "KEY_RESTORE_PUBLIC_KEY must be set to restore from KEY_RESTORE_SHARES".to_string(),
+                )
+            })?;
+            let shares = backup::read_shares(shares_path)?;
+            backup::restore_keystore(config, &shares, Some(expected_public_key))?;
+        }
+
+        // The key must survive restarts; a new one is only generated when no keystore exists yet.
+        // The tenant seed is created along with it; a missing seed next to an existing keystore
+        // would silently give every tenant a new address, so that is an error.
+        let keystore_exists = config.keystore_path.try_exists()?;
+        let keyring = Arc::new(RwLock::new(KeyRing::open(&config.keystore_path, &config.keystore_passphrase)?));
+        let hd_seed = if keystore_exists {
+            if !config.hd_seed_path.try_exists()? {
+                return Err(ZkpError::KeystoreError(format!(
+                    "HD seed {} is missing but keystore {} exists; restore the seed from a backup",
+                    config.hd_seed_path.display(),
+                    config.keystore_path.display()
+                )));
+            }
+            keystore::load_sized_secret(&config.hd_seed_path, &config.keystore_passphrase, hd::SEED_LEN)?
+        } else {
+            keystore::load_or_create_secret(&config.hd_seed_path, &config.keystore_passphrase, hd::SEED_LEN)?
+        };
+        let hd_master = Arc::new(ExtendedKey::master(&hd_seed)?);
         
-        let (task_sender, task_receiver) = mpsc::unbounded_channel::<QueuedProofTask>();
-        let active_proofs = Arc::new(Mutex::new(HashMap::<String, ProofTask>::new()));
+        let tasks: Arc<dyn TaskStore> = match &config.task_store_path {
+            Some(path) => Arc::new(FileTaskStore::open(path.clone())?),
+            None => Arc::new(MemoryTaskStore::default()),
+        };
         
-        // Start worker pool - share receiver across workers using Arc<tokio::sync::Mutex<>>
+        let webhooks = Arc::new(WebhookSender::new(
+            secp.clone(),
+            keyring.clone(),
+            tasks.clone(),
+            config.webhook_max_attempts,
+            Duration::from_secs(config.webhook_timeout_secs),
+        )?);
+
+        // Start worker pool, all pulling from the shared queue
         let num_workers = num_cpus::get();
-        let active_proofs_clone = active_proofs.clone();
+        let queue = Arc::new(TaskQueue::new(
+            config.task_queue_capacity,
+            num_workers,
+            Duration::from_secs(config.priority_aging_secs),
+        ));
+        let (events, _) = broadcast::channel(TASK_EVENT_CAPACITY);
         let mock_mode_clone = mock_mode;
-        let shared_receiver = Arc::new(tokio::sync::Mutex::new(task_receiver));
+        let proof_timeout_max_secs = config.proof_timeout_max_secs;
         
         for _ in 0..num_workers {
-            let receiver = shared_receiver.clone();
-            let proofs = active_proofs_clone.clone();
+            let queue = queue.clone();
+            let tasks = tasks.clone();
             let mock = mock_mode_clone;
+            let secp = secp.clone();
+            let keyring = keyring.clone();
+            let events = events.clone();
+            let webhooks = webhooks.clone();
             
             tokio::spawn(async move {
                 loop {
-                    let task = {
-                        let mut recv_guard = receiver.lock().await;
-                        recv_guard.recv().await
-                    };
+                    let (task, cancelled) = queue.pop().await;
+                    
+                    Self::set_status(&tasks, &events, &task.task_id, ProofStatus::InProgress).await;
                     
-                    let task = match task {
-                        Some(task) => task,
-                        None => break, // Channel closed
+                    // Process the proof and sign a receipt binding it to this service. Cancelling
+                    // or timing out drops the proving future, which kills a running nargo or bb child.
+                    let timeout_secs = task.timeout_secs.unwrap_or(proof_timeout_max_secs).min(proof_timeout_max_secs);
+                    let proving = Self::process_task(&secp, &keyring, &events, &task, mock);
+                    let status = tokio::select! {
+                        status = Self::prove_within(&queue, Duration::from_secs(timeout_secs), proving) => status,
+                        Ok(()) = cancelled => ProofStatus::Cancelled,
                     };
+                    queue.finish(&task.task_id);
                     
-                    // Update status to InProgress
-                    {
-                        let mut proofs = proofs.lock().unwrap();
-                        if let Some(proof_task) = proofs.get_mut(&task.task_id) {
-                            proof_task.status = ProofStatus::InProgress;
-                        }
+                    // Update status based on result, then tell the submitter if it asked for a callback
+                    if let Some(updated) = Self::set_status(&tasks, &events, &task.task_id, status).await {
+                        Self::notify_callback(&webhooks, &task.task_id, &updated);
                     }
-                    
-                    // Process the proof
-                    let result = if task.mock_mode || mock {
-                        Self::generate_mock_proof(&task.task_id, &task.input).await
-                    } else {
-                        Self::generate_noir_proof(&task.circuit_path, &task.input).await
+                }
+            });
+        }
+
+        // Re-run tasks that were queued or being proved when the service last stopped, and finish
+        // webhook deliveries that were cut short
+        let stored = tasks.list()?;
+        let batches = Arc::new(Mutex::new(Self::index_batches(&stored)));
+        let (mut unfinished, finished): (Vec<_>, Vec<_>) =
+            stored.into_iter().partition(|(_, task)| !task.status.is_finished());
+        for (task_id, task) in &finished {
+            Self::notify_callback(&webhooks, task_id, task);
+        }
+        unfinished.sort_by_key(|(_, task)| task.submitted_at);
+        for (task_id, mut task) in unfinished {
+            match task.queued.clone() {
+                Some(queued) => {
+                    task.status = ProofStatus::Pending;
+                    tasks.put(&task_id, &task)?;
+                    queue.requeue(queued);
+                }
+                None => {
+                    task.status = ProofStatus::Failed {
+                        error: "Task input was lost before it was proved".to_string(),
+                        code: Some("input_lost".to_string()),
                     };
-                    
-                    // Update status based on result
-                    {
-                        let mut proofs = proofs.lock().unwrap();
-                        if let Some(proof_task) = proofs.get_mut(&task.task_id) {
-                            match result {
-                                Ok(proof) => {
-                                    proof_task.status = ProofStatus::Completed { proof };
-                                }
-                                Err(e) => {
-                                    proof_task.status = ProofStatus::Failed {
-                                        error: e.to_string(),
-                                    };
-                                }
-                            }
-                        }
+                    task.finished_at = Some(chrono::Utc::now().timestamp());
+                    tasks.put(&task_id, &task)?;
+                    Self::notify_callback(&webhooks, &task_id, &task);
+                }
+            }
+        }
+
+        // Expired sessions are otherwise only dropped by the next handshake or request
+        let sessions = Arc::new(SessionManager::new(config.session_ttl_secs, config.session_max_count));
+        {
+            let sessions = sessions.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(SESSION_PRUNE_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    sessions.prune();
+                }
+            });
+        }
+
+        // Evict finished tasks according to the retention policy, then forget batches whose tasks
+        // have all been evicted
+        {
+            let tasks = tasks.clone();
+            let batches = batches.clone();
+            let retention = config.retention.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(retention.sweep_interval_secs));
+                loop {
+                    interval.tick().await;
+                    let tasks = tasks.clone();
+                    let batches = batches.clone();
+                    let retention = retention.clone();
+                    let sweep = move || {
+                        let expired = Self::sweep_tasks(tasks.as_ref(), &retention)?;
+                        Self::prune_batches(tasks.as_ref(), &batches)?;
+                        Ok::<_, ZkpError>(expired)
+                    };
+                    match tokio::task::spawn_blocking(sweep).await {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => eprintln!("Task retention sweep failed: {}", e),
+                        Err(e) => eprintln!("Task retention sweep panicked: {}", e),
                     }
                 }
             });
         }
         
         Ok(Self {
-            secp: Arc::new(secp),
-            secret_key: Arc::new(secret_key),
+            secp,
+            keyring,
+            key_rotation: Arc::new(Mutex::new(())),
+            hd_master,
             state: Arc::new(Mutex::new(HashMap::new())),
-            active_proofs,
-            task_sender,
+            tasks,
+            queue,
             mock_mode,
             tracked_directories: Arc::new(Mutex::new(HashMap::new())),
+            runtime_info: Arc::new(tokio::sync::OnceCell::new()),
+            proof_timeout_max_secs,
+            verify_permits: Arc::new(tokio::sync::Semaphore::new(config.verify_concurrency)),
+            sessions,
+            events,
+            batches,
         })
     }
 
+    // Batch id -> task ids ordered by their position in the batch
+    fn index_batches(tasks: &[(String, ProofTask)]) -> HashMap<String, Vec<String>> {
+        let mut members: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+        for (task_id, task) in tasks {
+            if let Some(batch) = &task.batch {
+                members.entry(batch.batch_id.clone()).or_default().push((batch.index, task_id.clone()));
+            }
+        }
+        members
+            .into_iter()
+            .map(|(batch_id, mut tasks)| {
+                tasks.sort();
+                (batch_id, tasks.into_iter().map(|(_, task_id)| task_id).collect())
+            })
+            .collect()
+    }
+
     pub fn get_public_key(&self) -> ZkpResult<String> {
-        let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, &self.secret_key);
-        let serialized = public_key.serialize_uncompressed();
-        Ok(format!("0x{}", hex::encode(&serialized)))
+        let keyring = self.keyring.read().unwrap();
+        let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, keyring.active_key());
+        Ok(ethereum::public_key_hex(&public_key))
+    }
+
+    /// Active public key plus every key version and transition, so signatures made by
+    /// retired keys can still be checked
+    pub fn get_key_info(&self) -> ZkpResult<PublicKeyResponse> {
+        let keyring = self.keyring.read().unwrap();
+        let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, keyring.active_key());
+        let history = keyring.history();
+
+        let keys = history
+            .keys
+            .iter()
+            .map(|info| {
+                let key = secp256k1::PublicKey::from_slice(&ethereum::decode_hex_bytes(&info.public_key)?)
+                    .map_err(|e| ZkpError::KeystoreError(format!("Invalid public key in key history: {}", e)))?;
+                Ok(PublicKeyEntry {
+                    info: info.clone(),
+                    compressed_public_key: ethereum::compressed_public_key_hex(&key),
+                    address: ethereum::format_address(&ethereum::address_from_public_key(&key)),
+                    fingerprint: ethereum::key_fingerprint(&key),
+                })
+            })
+            .collect::<ZkpResult<Vec<_>>>()?;
+
+        let encryption_keys = EncryptionSuite::ALL
+            .into_iter()
+            .map(|suite| {
+                Ok(EncryptionKeyInfo {
+                    suite: suite.id().to_string(),
+                    public_key: suite.public_key_hex(&self.secp, keyring.active_key())?,
+                })
+            })
+            .collect::<ZkpResult<Vec<_>>>()?;
+
+        Ok(PublicKeyResponse {
+            public_key: ethereum::public_key_hex(&public_key),
+            compressed_public_key: ethereum::compressed_public_key_hex(&public_key),
+            address: ethereum::format_address(&ethereum::address_from_public_key(&public_key)),
+            fingerprint: ethereum::key_fingerprint(&public_key),
+            key_version: keyring.active_version(),
+            keys,
+            transitions: history.transitions.clone(),
+            encryption_keys,
+        })
+    }
+
+    /// Retire the active key and replace it with a new one. The new keystore is encrypted and
+    /// written before the key ring is locked for writing, so signers only wait for the swap.
+    pub fn rotate_key(&self) -> ZkpResult<KeyTransition> {
+        let _rotating = self.key_rotation.lock().unwrap();
+        let rotation = self.keyring.read().unwrap().stage_rotation()?;
+        rotation.persist()?;
+        self.keyring.write().unwrap().commit_rotation(rotation)
+    }
+
+    /// Derive a tenant's key. Tenants only ever get keys under the fixed hardened tenant prefix,
+    /// never an arbitrary path, so no caller can reach another tenant's key or the master key.
+    fn derived_key(&self, tenant_id: &str) -> ZkpResult<(SecpSecretKey, String)> {
+        if tenant_id.is_empty() {
+            return Err(ZkpError::InvalidInput("tenant_id must not be empty".to_string()));
+        }
+        let path = hd::tenant_path(tenant_id);
+        let key = self.hd_master.derive_path(&self.secp, &path)?;
+        Ok((*key.secret_key(), path))
+    }
+
+    pub fn get_derived_public_key(&self, tenant_id: &str) -> ZkpResult<DerivedPublicKeyResponse> {
+        let (secret_key, path) = self.derived_key(tenant_id)?;
+        let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, &secret_key);
+
+        Ok(DerivedPublicKeyResponse {
+            public_key: ethereum::public_key_hex(&public_key),
+            compressed_public_key: ethereum::compressed_public_key_hex(&public_key),
+            address: ethereum::format_address(&ethereum::address_from_public_key(&public_key)),
+            fingerprint: ethereum::key_fingerprint(&public_key),
+            tenant_id: tenant_id.to_string(),
+            derivation_path: path,
+        })
     }
 
     /// Sign a message using Ethereum-compatible EIP-191 personal sign format
     /// Returns signature in format: 0x + r (32 bytes) + s (32 bytes) + v (1 byte) = 65 bytes total
-    pub fn sign_message(&self, message: &str) -> ZkpResult<String> {
-        // Ethereum personal sign format: "\x19Ethereum Signed Message:\n" + length + message
-        let prefix = "\x19Ethereum Signed Message:\n";
-        let message_bytes = message.as_bytes();
-        let length = message_bytes.len();
-        
-        // Create the prefixed message
-        let mut prefixed_message = Vec::with_capacity(prefix.len() + 8 + message_bytes.len());
-        prefixed_message.extend_from_slice(prefix.as_bytes());
-        prefixed_message.extend_from_slice(length.to_string().as_bytes());
-        prefixed_message.extend_from_slice(message_bytes);
-        
-        // Hash with Keccak256
-        let mut hasher = Keccak256::new();
-        hasher.update(&prefixed_message);
-        let hash = hasher.finalize();
-        
-        // Convert to secp256k1 Message (must be exactly 32 bytes)
-        let message_hash = Message::from_digest_slice(&hash)
-            .map_err(|e| ZkpError::StateError(format!("Failed to create message hash: {}", e)))?;
-        
-        // Sign the message with recovery
-        let signature = self.secp.sign_ecdsa_recoverable(&message_hash, &self.secret_key);
-        
-        // Get recovery ID
-        let recovery_id = signature.serialize_compact().0;
-        
-        // Serialize signature to compact format (64 bytes: r + s)
-        let sig_compact = signature.serialize_compact().1;
-        
-        // Extract r and s (32 bytes each)
-        let r = &sig_compact[0..32];
-        let s = &sig_compact[32..64];
-        
-        // Calculate v (recovery ID + 27 for Ethereum)
-        let v = recovery_id.to_i32() as u8 + 27;
-        
-        // Combine r + s + v
-        let mut signature_bytes = Vec::with_capacity(65);
-        signature_bytes.extend_from_slice(r);
-        signature_bytes.extend_from_slice(s);
-        signature_bytes.push(v);
-        
-        Ok(format!("0x{}", hex::encode(&signature_bytes)))
+    /// Signs with the tenant key when a tenant id is given
+    pub fn sign_message(&self, message: &str, tenant_id: Option<&str>) -> ZkpResult<SignMessageResponse> {
+        if let Some(tenant_id) = tenant_id {
+            let (secret_key, path) = self.derived_key(tenant_id)?;
+            let signature = ethereum::sign_personal_message(&self.secp, &secret_key, message.as_bytes())?;
+            let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, &secret_key);
+            return Ok(SignMessageResponse {
+                signature,
+                public_key: ethereum::public_key_hex(&public_key),
+                key_version: None,
+                derivation_path: Some(path),
+            });
+        }
+
+        let keyring = self.keyring.read().unwrap();
+        let signature = ethereum::sign_personal_message(&self.secp, keyring.active_key(), message.as_bytes())?;
+        let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, keyring.active_key());
+
+        Ok(SignMessageResponse {
+            signature,
+            public_key: ethereum::public_key_hex(&public_key),
+            key_version: Some(keyring.active_version()),
+            derivation_path: None,
+        })
     }
 
-    pub async fn execute_zkp(&self, request: ProofRequest) -> ZkpResult<ProofResponse> {
-        let task_id = format!("proof_{}", uuid::Uuid::new_v4().to_string().replace("-", ""));
-        
-        // Register task as pending
-        {
-            let mut proofs = self.active_proofs.lock().unwrap();
-            proofs.insert(
-                task_id.clone(),
-                ProofTask {
-                    status: ProofStatus::Pending,
-                },
-            );
+    /// Sign EIP-712 typed data (the `eth_signTypedData_v4` digest)
+    /// Returns the signature in the same r + s + v format as `sign_message`
+    pub fn sign_typed_data(&self, typed_data: &TypedData) -> ZkpResult<SignTypedDataResponse> {
+        let digest = typed_data.signing_hash()?;
+        let keyring = self.keyring.read().unwrap();
+        let signature = ethereum::sign_digest(&self.secp, keyring.active_key(), &digest)?;
+        let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, keyring.active_key());
+
+        Ok(SignTypedDataResponse {
+            signature: format!("0x{}", hex::encode(signature)),
+            digest: format!("0x{}", hex::encode(digest)),
+            public_key: ethereum::public_key_hex(&public_key),
+            key_version: keyring.active_version(),
+        })
+    }
+
+    /// Sign an EIP-1559 transaction with the service key, returning the raw transaction ready
+    /// for `eth_sendRawTransaction`
+    pub fn sign_transaction(&self, transaction: &Eip1559Transaction) -> ZkpResult<SignTransactionResponse> {
+        let keyring = self.keyring.read().unwrap();
+        let signed = transaction.sign(&self.secp, keyring.active_key())?;
+        let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, keyring.active_key());
+
+        Ok(SignTransactionResponse {
+            raw_transaction: format!("0x{}", hex::encode(&signed.raw_transaction)),
+            transaction_hash: format!("0x{}", hex::encode(signed.transaction_hash)),
+            signing_hash: format!("0x{}", hex::encode(signed.signing_hash)),
+            from: ethereum::format_address(&ethereum::address_from_public_key(&public_key)),
+            key_version: keyring.active_version(),
+        })
+    }
+
+    /// Recover the signer of an EIP-191 message or a raw digest and optionally compare it
+    /// with an expected address
+    pub fn verify_signature(&self, request: &VerifySignatureRequest) -> ZkpResult<VerifySignatureResponse> {
+        let digest = match (&request.message, &request.digest) {
+            (Some(message), None) => ethereum::personal_message_hash(message.as_bytes()),
+            (None, Some(digest)) => ethereum::decode_hex_bytes(digest)?
+                .try_into()
+                .map_err(|_| ZkpError::InvalidInput("Digest must be 32 bytes".to_string()))?,
+            _ => {
+                return Err(ZkpError::InvalidInput(
+                    "Exactly one of message or digest must be provided".to_string(),
+                ))
+            }
+        };
+
+        let signature = ethereum::decode_hex_bytes(&request.signature)?;
+        let public_key = ethereum::recover_public_key(&self.secp, &digest, &signature)?;
+        let address = ethereum::address_from_public_key(&public_key);
+
+        let matches_expected = request
+            .expected_signer
+            .as_deref()
+            .map(|expected| ethereum::parse_address(expected).map(|expected| expected == address))
+            .transpose()?;
+
+        Ok(VerifySignatureResponse {
+            recovered_address: ethereum::format_address(&address),
+            recovered_public_key: ethereum::public_key_hex(&public_key),
+            digest: format!("0x{}", hex::encode(digest)),
+            matches_expected,
+        })
+    }
+
+    /// Sign a document describing this runtime (key, build, mode and prover toolchain),
+    /// bound to the client's nonce so it cannot be replayed
+    pub async fn attest(&self, nonce: &str) -> ZkpResult<AttestationResponse> {
+        if nonce.is_empty() || nonce.len() > 256 {
+            return Err(ZkpError::InvalidInput("Nonce must be between 1 and 256 characters".to_string()));
         }
+        let runtime_info = self.runtime_info.get_or_try_init(Self::probe_runtime_info).await?;
 
-        // Enqueue task for processing
-        let queued_task = QueuedProofTask {
-            task_id: task_id.clone(),
-            circuit_path: request.circuit_path.clone(),
-            input: request.input.clone(),
-            mock_mode: self.mock_mode || request.mock,
+        let keyring = self.keyring.read().unwrap();
+        let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, keyring.active_key());
+        let document = AttestationDocument {
+            nonce: nonce.to_string(),
+            public_key: ethereum::public_key_hex(&public_key),
+            address: ethereum::format_address(&ethereum::address_from_public_key(&public_key)),
+            key_version: keyring.active_version(),
+            crate_name: env!("CARGO_PKG_NAME").to_string(),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            binary_hash: runtime_info.binary_hash.clone(),
+            mock_mode: self.mock_mode,
+            prover_backends: runtime_info.prover_backends.clone(),
+            toolchain: runtime_info.toolchain.clone(),
+            issued_at: chrono::Utc::now().to_rfc3339(),
         };
+        let payload = serde_json::to_string(&document)?;
+        let signature = ethereum::sign_personal_message(&self.secp, keyring.active_key(), payload.as_bytes())?;
+
+        Ok(AttestationResponse {
+            document,
+            payload,
+            signature,
+        })
+    }
+
+    async fn probe_runtime_info() -> ZkpResult<RuntimeInfo> {
+        let exe = std::env::current_exe()?;
+        let binary = tokio::fs::read(&exe).await?;
+        let binary_hash = format!("0x{}", hex::encode(Sha256::digest(&binary)));
+
+        let mut toolchain = BTreeMap::new();
+        for tool in ["nargo", "bb", "git"] {
+            toolchain.insert(tool.to_string(), Self::tool_version(tool).await);
+        }
+
+        let mut prover_backends = vec!["mock".to_string()];
+        if toolchain.get("nargo").is_some_and(Option::is_some) {
+            prover_backends.push("nargo".to_string());
+        }
 
-        self.task_sender
-            .send(queued_task)
-            .map_err(|e| ZkpError::StateError(format!("Failed to enqueue task: {}", e)))?;
+        Ok(RuntimeInfo {
+            binary_hash,
+            prover_backends,
+            toolchain,
+        })
+    }
 
+    async fn tool_version(tool: &str) -> Option<String> {
+        let output = Command::new(tool).arg("--version").output().await.ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Some(version)
+    }
+
+    /// Open an encrypted session for a client ephemeral key. The server's ephemeral key is
+    /// signed with the service key so the client knows who it is talking to.
+    pub fn open_session(&self, client_public_key: &str) -> ZkpResult<SessionHandshakeResponse> {
+        let client_public_key = secp256k1::PublicKey::from_slice(&ethereum::decode_hex_bytes(client_public_key)?)
+            .map_err(|e| ZkpError::InvalidInput(format!("Invalid client public key: {}", e)))?;
+        let handshake = self.sessions.open(&client_public_key)?;
+
+        let mut encoded = b"zkpruntime session v1".to_vec();
+        encoded.extend_from_slice(handshake.session_id.as_bytes());
+        encoded.extend_from_slice(&client_public_key.serialize());
+        encoded.extend_from_slice(&handshake.server_public_key.serialize());
+        let mut expires_word = [0u8; 32];
+        expires_word[24..].copy_from_slice(&(handshake.expires_at as u64).to_be_bytes());
+        encoded.extend_from_slice(&expires_word);
+        let digest = ethereum::keccak256(&encoded);
+
+        let keyring = self.keyring.read().unwrap();
+        let signature = ethereum::sign_personal_message(&self.secp, keyring.active_key(), &digest)?;
+        let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, keyring.active_key());
+
+        Ok(SessionHandshakeResponse {
+            session_id: handshake.session_id,
+            server_public_key: ethereum::compressed_public_key_hex(&handshake.server_public_key),
+            expires_at: handshake.expires_at,
+            digest: format!("0x{}", hex::encode(digest)),
+            signature,
+            signer: ethereum::format_address(&ethereum::address_from_public_key(&public_key)),
+            key_version: keyring.active_version(),
+        })
+    }
+
+    pub async fn execute_zkp(&self, request: ProofRequest) -> ZkpResult<ProofResponse> {
+        let (queued_task, task, input_salt) = self.prepare_task(request, None)?;
+        let task_id = queued_task.task_id.clone();
+
+        // A full queue rejects the task outright, before anything is stored
+        let slot = self.queue.reserve(1)?;
+
+        // Register task as pending; the stored copy lets it be re-run after a restart
+        let id = task_id.clone();
+        task_store::run_blocking(&self.tasks, move |tasks| tasks.put(&id, &task)).await?;
+        slot.push_all(vec![queued_task]);
+
+        let (queue_position, estimated_wait_ms) = Self::queue_estimate(&self.queue, &task_id);
         Ok(ProofResponse {
             task_id: task_id.clone(),
             status: "pending".to_string(),
             proof: None,
+            receipt: None,
+            sealed_output: None,
             error: None,
+            error_code: None,
+            queue_position,
+            estimated_wait_ms,
+            webhook_attempts: Vec::new(),
+            input_salt: Some(format!("0x{}", hex::encode(input_salt))),
         })
     }
 
-    async fn generate_noir_proof(circuit_path: &str, input: &serde_json::Value) -> ZkpResult<String> {
-        let input_str = serde_json::to_string(input)?;
-        let proof_path = format!("{}.proof", circuit_path);
+    /// Submit many proof requests at once. The batch is accepted or rejected as a whole: every
+    /// request is validated, and the queue must have room for all of them.
+    pub async fn execute_zkp_batch(&self, request: BatchProofRequest) -> ZkpResult<BatchSubmitResponse> {
+        if request.requests.is_empty() || request.requests.len() > MAX_BATCH_SIZE {
+            return Err(ZkpError::InvalidInput(format!(
+                "A batch must hold between 1 and {} requests",
+                MAX_BATCH_SIZE
+            )));
+        }
+        let batch_id = format!("batch_{}", uuid::Uuid::new_v4().simple());
+
+        let prepared = request
+            .requests
+            .into_iter()
+            .enumerate()
+            .map(|(index, request)| {
+                let batch = BatchRef {
+                    batch_id: batch_id.clone(),
+                    index,
+                };
+                self.prepare_task(request, Some(batch))
+                    .map_err(|e| ZkpError::InvalidInput(format!("Batch request {}: {}", index, e)))
+            })
+            .collect::<ZkpResult<Vec<_>>>()?;
+        let task_ids: Vec<String> = prepared.iter().map(|(queued, _, _)| queued.task_id.clone()).collect();
+        let input_salts = prepared.iter().map(|(_, _, salt)| format!("0x{}", hex::encode(salt))).collect();
+
+        let (queued_tasks, stored_tasks): (Vec<_>, Vec<_>) =
+            prepared.into_iter().map(|(queued, stored, _)| (queued, stored)).unzip();
+        let stored_tasks: Vec<_> = task_ids.iter().cloned().zip(stored_tasks).collect();
+        let slots = self.queue.reserve(queued_tasks.len())?;
+        task_store::run_blocking(&self.tasks, move |tasks| Self::put_all(tasks, &stored_tasks)).await?;
+        slots.push_all(queued_tasks);
+
+        self.batches.lock().unwrap().insert(batch_id.clone(), task_ids.clone());
+        Ok(BatchSubmitResponse {
+            batch_id,
+            task_ids,
+            input_salts,
+        })
+    }
+
+    // Store all tasks or none: a failed write removes the ones already written
+    fn put_all(tasks: &dyn TaskStore, batch: &[(String, ProofTask)]) -> ZkpResult<()> {
+        for (written, (task_id, task)) in batch.iter().enumerate() {
+            if let Err(e) = tasks.put(task_id, task) {
+                for (task_id, _) in &batch[..written] {
+                    if let Err(e) = tasks.remove(task_id) {
+                        eprintln!("Failed to roll back batch task {}: {}", task_id, e);
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts of the batch's tasks per status and, once every task has finished, all results in
+    /// submission order
+    pub async fn retrieve_batch(&self, batch_id: &str) -> ZkpResult<BatchStatusResponse> {
+        let task_ids = self
+            .batches
+            .lock()
+            .unwrap()
+            .get(batch_id)
+            .cloned()
+            .ok_or_else(|| ZkpError::InvalidInput(format!("Batch {} not found", batch_id)))?;
+        let queue = self.queue.clone();
+        let batch_id = batch_id.to_string();
+        task_store::run_blocking(&self.tasks, move |tasks| Self::batch_status(tasks, &queue, batch_id, task_ids)).await
+    }
+
+    fn batch_status(
+        tasks: &dyn TaskStore,
+        queue: &TaskQueue,
+        batch_id: String,
+        task_ids: Vec<String>,
+    ) -> ZkpResult<BatchStatusResponse> {
+        let mut counts = BTreeMap::new();
+        let mut results = Vec::with_capacity(task_ids.len());
+        let mut finished = true;
+        for task_id in &task_ids {
+            let response = match tasks.get(task_id)? {
+                Some(task) => {
+                    finished &= task.status.is_finished();
+                    Self::task_response(tasks, queue, task_id)?
+                }
+                // Expired and its marker since dropped by the retention sweeper
+                None => ProofResponse {
+                    task_id: task_id.clone(),
+                    status: "missing".to_string(),
+                    proof: None,
+                    receipt: None,
+                    sealed_output: None,
+                    error: Some("Task is no longer stored".to_string()),
+                    error_code: Some("missing".to_string()),
+                    queue_position: None,
+                    estimated_wait_ms: None,
+                    webhook_attempts: Vec::new(),
+                    input_salt: None,
+                },
+            };
+            *counts.entry(response.status.clone()).or_insert(0) += 1;
+            results.push(response);
+        }
+
+        Ok(BatchStatusResponse {
+            batch_id,
+            total: task_ids.len(),
+            counts,
+            finished,
+            results: finished.then_some(results),
+        })
+    }
+
+    /// Validate a request and build its queue entry, its stored task and its commitment salt
+    fn prepare_task(
+        &self,
+        request: ProofRequest,
+        batch: Option<BatchRef>,
+    ) -> ZkpResult<(QueuedProofTask, ProofTask, [u8; 32])> {
+        // A mock proof gets a signed receipt, so only a mock-mode service may make one
+        if request.mock && !self.mock_mode {
+            return Err(ZkpError::InvalidInput("Mock proofs are disabled on this service".to_string()));
+        }
+        // The key version is recorded so the input can still be opened after a rotation
+        let (key_version, public_key) = {
+            let keyring = self.keyring.read().unwrap();
+            let key_version = request
+                .encrypted_input
+                .as_ref()
+                .and_then(|envelope| envelope.key_version)
+                .unwrap_or_else(|| keyring.active_version());
+            let key = keyring.key(key_version).map_err(|e| ZkpError::InvalidInput(e.to_string()))?;
+            (key_version, secp256k1::PublicKey::from_secret_key(&self.secp, key))
+        };
+        let (suite, payload) = match (request.input, request.encrypted_input) {
+            // Plaintext inputs are sealed to the service key straight away so the queue and the
+            // task store never hold them in the clear
+            (Some(input), None) => (
+                EncryptionSuite::Secp256k1Ecies,
+                ecies::encrypt(&public_key, &serde_json::to_vec(&input)?, PROOF_INPUT_CONTEXT)?,
+            ),
+            (None, Some(envelope)) => (
+                EncryptionSuite::from_id(envelope.suite.as_deref())?,
+                hex::decode(envelope.ciphertext.trim_start_matches("0x"))
+                    .map_err(|e| ZkpError::InvalidInput(format!("Invalid encrypted input: {}", e)))?,
+            ),
+            _ => {
+                return Err(ZkpError::InvalidInput(
+                    "Provide exactly one of input and encrypted_input".to_string(),
+                ))
+            }
+        };
+        // Witnesses are often low-entropy, so the published commitment is salted
+        let mut salt = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let input = TaskInput {
+            suite,
+            key_version: Some(key_version),
+            payload,
+            sealed_salt: ecies::encrypt(&public_key, &salt, INPUT_SALT_CONTEXT)?,
+        };
+        let recipient = request
+            .recipient_public_key
+            .as_deref()
+            .map(|key| {
+                secp256k1::PublicKey::from_slice(&ethereum::decode_hex_bytes(key)?)
+                    .map_err(|e| ZkpError::InvalidInput(format!("Invalid recipient public key: {}", e)))
+            })
+            .transpose()?;
+        let callback_url = request.callback_url.as_deref().map(webhook::parse_callback_url).transpose()?;
+        let task_id = format!("proof_{}", uuid::Uuid::new_v4().to_string().replace("-", ""));
         
-        let output = Command::new("nargo")
-            .arg("prove")
-            .arg("--proof-path")
-            .arg(&proof_path)
-            .arg("--witness")
-            .arg(format!("{}.witness", circuit_path))
-            .arg("--program")
-            .arg(circuit_path)
-            .arg("--input")
-            .arg(&input_str)
-            .output()
+        let queued_task = QueuedProofTask {
+            task_id: task_id.clone(),
+            circuit_path: request.circuit_path.clone(),
+            input,
+            mock_mode: self.mock_mode,
+            recipient,
+            timeout_secs: request.timeout_secs.map(|secs| secs.clamp(1, self.proof_timeout_max_secs)),
+            priority: request.priority,
+        };
+
+        let task = ProofTask {
+            status: ProofStatus::Pending,
+            submitted_at: chrono::Utc::now().timestamp(),
+            queued: Some(queued_task.clone()),
+            finished_at: None,
+            callback_url,
+            webhook_attempts: Vec::new(),
+            batch,
+        };
+        Ok((queued_task, task, salt))
+    }
+
+    /// Cancel a queued or running task
+    pub async fn cancel_task(&self, task_id: &str) -> ZkpResult<ProofResponse> {
+        let id = task_id.to_string();
+        let task = task_store::run_blocking(&self.tasks, move |tasks| tasks.get(&id))
+            .await?
+            .ok_or_else(|| ZkpError::InvalidInput(format!("Task {} not found", task_id)))?;
+        match self.queue.cancel(task_id) {
+            Cancellation::Dequeued | Cancellation::Signalled => {
+                Self::set_status(&self.tasks, &self.events, task_id, ProofStatus::Cancelled).await;
+            }
+            Cancellation::NotActive => {
+                return Err(ZkpError::InvalidInput(format!(
+                    "Task {} is not queued or running ({})",
+                    task_id,
+                    task.status.name()
+                )));
+            }
+        }
+        self.retrieve_output(task_id).await
+    }
+
+    fn queue_estimate(queue: &TaskQueue, task_id: &str) -> (Option<usize>, Option<u64>) {
+        let position = queue.position(task_id);
+        let wait = position
+            .and_then(|position| queue.estimated_wait(position))
+            .map(|wait| wait.as_millis() as u64);
+        (position, wait)
+    }
+
+    /// Replace finished tasks that are too old, or beyond the count or size limits (oldest
+    /// first), with expired markers, and drop markers older than the tombstone period.
+    /// Returns the number of tasks expired.
+    fn sweep_tasks(tasks: &dyn TaskStore, retention: &RetentionPolicy) -> ZkpResult<usize> {
+        let now = chrono::Utc::now().timestamp();
+        let mut finished = Vec::new();
+        for (task_id, task) in tasks.list()? {
+            match task.status {
+                ProofStatus::Expired { expired_at } => {
+                    if now - expired_at > retention.tombstone_secs as i64 {
+                        tasks.remove(&task_id)?;
+                    }
+                }
+                ProofStatus::Pending | ProofStatus::InProgress => {}
+                _ => {
+                    let finished_at = task.finished_at.unwrap_or(task.submitted_at);
+                    let size = serde_json::to_vec(&task)?.len() as u64;
+                    finished.push((task_id, task, finished_at, size));
+                }
+            }
+        }
+
+        // Newest first, so the count and size limits keep the most recent results. Once a task
+        // does not fit, every older one goes too, however small.
+        finished.sort_by_key(|(_, _, finished_at, _)| std::cmp::Reverse(*finished_at));
+        let mut kept_count = 0usize;
+        let mut kept_bytes = 0u64;
+        let mut full = false;
+        let mut expired = 0;
+        for (task_id, _, finished_at, size) in finished {
+            full = full || retention.max_bytes.is_some_and(|max| kept_bytes + size > max);
+            let keep = !full
+                && now - finished_at <= retention.max_age_secs as i64
+                && retention.max_count.is_none_or(|max| kept_count < max);
+            if keep {
+                kept_count += 1;
+                kept_bytes += size;
+                continue;
+            }
+            let updated = tasks.update(
+                &task_id,
+                Box::new(|task| {
+                    if matches!(task.status, ProofStatus::Expired { .. }) {
+                        return false;
+                    }
+                    task.status = ProofStatus::Expired { expired_at: now };
+                    true
+                }),
+            )?;
+            expired += updated.is_some() as usize;
+        }
+        Ok(expired)
+    }
+
+    /// Drop batches whose tasks have all expired or been removed
+    fn prune_batches(tasks: &dyn TaskStore, batches: &Mutex<HashMap<String, Vec<String>>>) -> ZkpResult<()> {
+        let snapshot: Vec<_> = batches
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(batch_id, task_ids)| (batch_id.clone(), task_ids.clone()))
+            .collect();
+        for (batch_id, task_ids) in snapshot {
+            let mut evicted = true;
+            for task_id in &task_ids {
+                if tasks.get(task_id)?.is_some_and(|task| !matches!(task.status, ProofStatus::Expired { .. })) {
+                    evicted = false;
+                    break;
+                }
+            }
+            if evicted {
+                batches.lock().unwrap().remove(&batch_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Record a status change. Finished tasks drop their queued input, and a finished status is
+    /// never overwritten (so a cancelled task stays cancelled even if its proof completes).
+    /// Returns the updated task, or `None` if nothing changed.
+    async fn set_status(
+        tasks: &Arc<dyn TaskStore>,
+        events: &broadcast::Sender<TaskEvent>,
+        task_id: &str,
+        status: ProofStatus,
+    ) -> Option<ProofTask> {
+        let id = task_id.to_string();
+        let result = task_store::run_blocking(tasks, move |tasks| {
+            tasks.update(
+                &id,
+                Box::new(|task| {
+                    if task.finished_at.is_some() {
+                        return false;
+                    }
+                    if status.is_finished() {
+                        task.queued = None;
+                        task.finished_at = Some(chrono::Utc::now().timestamp());
+                    }
+                    task.status = status;
+                    true
+                }),
+            )
+        })
+        .await;
+        match result {
+            Ok(Some(task)) => {
+                // Nobody may be listening; that is fine
+                let _ = events.send(Self::task_event(Self::status_response(task_id, &task.status), None));
+                Some(task)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                eprintln!("Failed to update task {}: {}", task_id, e);
+                None
+            }
+        }
+    }
+
+    /// Start delivering the result of a completed or failed task to its callback URL, unless it
+    /// has none or delivery already finished
+    fn notify_callback(webhooks: &Arc<WebhookSender>, task_id: &str, task: &ProofTask) {
+        let Some(url) = &task.callback_url else {
+            return;
+        };
+        let reportable = matches!(
+            task.status,
+            ProofStatus::Completed { .. } | ProofStatus::CompletedSealed { .. } | ProofStatus::Failed { .. }
+        );
+        if !reportable || !webhooks.is_pending(&task.webhook_attempts) {
+            return;
+        }
+        match serde_json::to_vec(&Self::status_response(task_id, &task.status)) {
+            Ok(body) => webhooks.spawn(task_id.to_string(), url.clone(), body, task.webhook_attempts.len() as u32),
+            Err(e) => eprintln!("Failed to encode webhook for task {}: {}", task_id, e),
+        }
+    }
+
+    /// Final status of a proving run cut off after `timeout`; only successes feed wait estimates
+    async fn prove_within(
+        queue: &TaskQueue,
+        timeout: Duration,
+        proving: impl Future<Output = ZkpResult<ProofStatus>>,
+    ) -> ProofStatus {
+        let started = Instant::now();
+        let result = tokio::time::timeout(timeout, proving)
             .await
-            .map_err(|e| ZkpError::NoirCommandError(format!("Failed to execute nargo: {}", e)))?;
+            .unwrap_or_else(|_| Err(ZkpError::Timeout(format!("Proving exceeded {}s", timeout.as_secs()))));
+        match result {
+            Ok(status) => {
+                queue.record_duration(started.elapsed());
+                status
+            }
+            Err(e) => ProofStatus::Failed {
+                error: e.to_string(),
+                code: Some(e.code().to_string()),
+            },
+        }
+    }
+
+    /// Open the task input, prove it and sign the receipt. Decrypted input lives only for the
+    /// duration of this call; everything kept afterwards refers to it by commitment. With a
+    /// recipient, the proof and receipt are sealed to it before they are stored.
+    async fn process_task(
+        secp: &Secp256k1<secp256k1::All>,
+        keyring: &RwLock<KeyRing>,
+        events: &broadcast::Sender<TaskEvent>,
+        task: &QueuedProofTask,
+        mock: bool,
+    ) -> ZkpResult<ProofStatus> {
+        let phase = |phase| {
+            let status = Self::status_response(&task.task_id, &ProofStatus::InProgress);
+            let _ = events.send(Self::task_event(status, Some(phase)));
+        };
+
+        // Tasks queued by an earlier mock-mode run are never signed as mock proofs here
+        if task.mock_mode && !mock {
+            return Err(ZkpError::InvalidInput("Mock proofs are disabled on this service".to_string()));
+        }
+
+        phase(ProofPhase::WitnessGeneration);
+        let (input, input_commitment) = Self::open_input(keyring, &task.input)?;
+        let proof = if mock {
+            drop(input);
+            phase(ProofPhase::Proving);
+            let proof = Self::generate_mock_proof(&task.task_id, &input_commitment).await?;
+            phase(ProofPhase::Verifying);
+            if let Some(reason) = Self::verify_mock_proof(&proof, &[input_commitment]).reason {
+                return Err(ZkpError::ProofGenerationError(format!(
+                    "Generated proof does not verify: {}",
+                    reason
+                )));
+            }
+            proof
+        } else {
+            let scratch = ScratchDir::create_async().await?;
+            let witness = Self::generate_witness(&scratch, &task.circuit_path, &input).await?;
+            drop(input);
+            phase(ProofPhase::Proving);
+            let artifact = compiled_circuit(&task.circuit_path)?;
+            Self::generate_noir_proof(&scratch, &artifact, &witness).await?;
+            phase(ProofPhase::Verifying);
+            Self::verify_noir_proof(&scratch, &artifact).await?
+        };
+
+        let receipt = Self::build_receipt(secp, keyring, task, mock, &input_commitment, &proof)?;
+        let Some(recipient) = &task.recipient else {
+            return Ok(ProofStatus::Completed { proof, receipt });
+        };
+
+        let output = SealedProofOutput {
+            task_id: task.task_id.clone(),
+            proof,
+            receipt,
+        };
+        let sealed_output = ecies::encrypt(recipient, &serde_json::to_vec(&output)?, b"")?;
+        Ok(ProofStatus::CompletedSealed {
+            sealed_output: format!("0x{}", hex::encode(sealed_output)),
+        })
+    }
+
+    /// Decrypt the input and commit to it: keccak256(salt ‖ decrypted plaintext), where the
+    /// plaintext of a plaintext submission is its serialized JSON
+    fn open_input(keyring: &RwLock<KeyRing>, input: &TaskInput) -> ZkpResult<(serde_json::Value, [u8; 32])> {
+        let key = {
+            let keyring = keyring.read().unwrap();
+            *keyring.key(input.key_version.unwrap_or_else(|| keyring.active_version()))?
+        };
+        let plaintext = input.suite.decrypt(&key, &input.payload, PROOF_INPUT_CONTEXT)?;
+        let value = serde_json::from_slice(&plaintext)
+            .map_err(|e| ZkpError::DecryptionError(format!("Decrypted input is not valid JSON: {}", e)))?;
+        let mut committed = if input.sealed_salt.is_empty() {
+            Vec::new()
+        } else {
+            EncryptionSuite::Secp256k1Ecies.decrypt(&key, &input.sealed_salt, INPUT_SALT_CONTEXT)?
+        };
+        committed.extend_from_slice(&plaintext);
+        Ok((value, ethereum::keccak256(&committed)))
+    }
+
+    // Solve the circuit for the input with `nargo execute`. The input goes to nargo as a private
+    // prover file, never on its command line where other local processes could read it, and the
+    // witness is written next to it.
+    async fn generate_witness(
+        scratch: &ScratchDir,
+        circuit_path: &str,
+        input: &serde_json::Value,
+    ) -> ZkpResult<PathBuf> {
+        if !Path::new(circuit_path).is_dir() {
+            return Err(ZkpError::InvalidInput(format!(
+                "Proving needs a Noir project directory, got {}",
+                circuit_path
+            )));
+        }
+        let prover_toml = toml::to_string(input)
+            .map_err(|e| ZkpError::InvalidInput(format!("Input cannot be written as Prover.toml: {}", e)))?;
+        scratch.write_private("Prover.toml", prover_toml.into_bytes()).await?;
 
+        let mut command = Command::new("nargo");
+        command
+            .arg("execute")
+            // Absolute witness and prover names are used as is instead of relative to the project
+            .arg(scratch.0.join("witness"))
+            .arg("--program-dir")
+            .arg(circuit_path)
+            .arg("--prover-name")
+            .arg(scratch.0.join("Prover"));
+        let output = Self::run_backend(command, "nargo").await?;
+        if !output.status.success() {
+            return Err(ZkpError::ProofGenerationError(format!(
+                "Witness generation failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(scratch.0.join("witness.gz"))
+    }
+
+    // `bb prove`, leaving `proof` and `public_inputs` in the scratch directory
+    async fn generate_noir_proof(scratch: &ScratchDir, artifact: &Path, witness: &Path) -> ZkpResult<()> {
+        let mut command = Command::new("bb");
+        command.arg("prove").arg("-b").arg(artifact).arg("-w").arg(witness).arg("-o").arg(&scratch.0);
+        let output = Self::run_backend(command, "bb").await?;
         if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
             return Err(ZkpError::ProofGenerationError(format!(
                 "Noir proof generation failed: {}",
-                stderr
+                String::from_utf8_lossy(&output.stderr).trim()
             )));
         }
+        Ok(())
+    }
 
-        let proof = std::fs::read_to_string(&proof_path)
+    // Check a fresh proof against the circuit's verification key before it is signed, and return
+    // it hex encoded
+    async fn verify_noir_proof(scratch: &ScratchDir, artifact: &Path) -> ZkpResult<String> {
+        let proof_path = scratch.0.join("proof");
+        let vk_path = Self::write_vk(artifact, &scratch.0).await?;
+        let output = Self::run_verifier(&vk_path, &proof_path, &scratch.0.join("public_inputs")).await?;
+        if let Some(reason) = verifier_rejection(&output) {
+            return Err(ZkpError::ProofGenerationError(format!(
+                "Generated proof does not verify: {}",
+                reason
+            )));
+        }
+        let proof = tokio::fs::read(&proof_path)
+            .await
             .map_err(|e| ZkpError::ProofGenerationError(format!("Failed to read proof file: {}", e)))?;
+        Ok(format!("0x{}", hex::encode(proof)))
+    }
 
-        Ok(proof)
+    // `bb write_vk`, leaving the key at `<out_dir>/vk`
+    async fn write_vk(artifact: &Path, out_dir: &Path) -> ZkpResult<PathBuf> {
+        let mut command = Command::new("bb");
+        command.arg("write_vk").arg("-b").arg(artifact).arg("-o").arg(out_dir);
+        let output = Self::run_backend(command, "bb").await?;
+        if !output.status.success() {
+            return Err(ZkpError::NoirCommandError(format!(
+                "Failed to derive the verification key: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(out_dir.join("vk"))
+    }
+
+    async fn run_verifier(vk: &Path, proof: &Path, public_inputs: &Path) -> ZkpResult<std::process::Output> {
+        let mut command = Command::new("bb");
+        command.arg("verify").arg("-k").arg(vk).arg("-p").arg(proof).arg("-i").arg(public_inputs);
+        Self::run_backend(command, "bb").await
     }
 
-    async fn generate_mock_proof(task_id: &str, input: &serde_json::Value) -> ZkpResult<String> {
+    /// Check a proof against a verification key, or against the key of a compiled circuit. In
+    /// mock mode, proofs made by `generate_mock_proof` are checked instead.
+    pub async fn verify_proof(&self, request: VerifyProofRequest) -> ZkpResult<VerifyProofResponse> {
+        if request.circuit_path.is_some() == request.verification_key.is_some() {
+            return Err(ZkpError::InvalidInput(
+                "Provide exactly one of circuit_path and verification_key".to_string(),
+            ));
+        }
+        let public_inputs = request
+            .public_inputs
+            .iter()
+            .map(|input| parse_field_element(input))
+            .collect::<ZkpResult<Vec<_>>>()?;
+
+        // Mock proofs are made up, so only a mock-mode service vouches for them
+        if self.mock_mode {
+            return Ok(Self::verify_mock_proof(&request.proof, &public_inputs));
+        }
+        if request.mock {
+            return Err(ZkpError::InvalidInput("Mock proofs are disabled on this service".to_string()));
+        }
+
+        let proof = ethereum::decode_hex_bytes(request.proof.trim())
+            .map_err(|e| ZkpError::InvalidInput(format!("Invalid proof: {}", e)))?;
+        let verification_key = request
+            .verification_key
+            .as_deref()
+            .map(|key| {
+                ethereum::decode_hex_bytes(key.trim())
+                    .map_err(|e| ZkpError::InvalidInput(format!("Invalid verification key: {}", e)))
+            })
+            .transpose()?;
+
+        // Verifiers are bounded like provers are; waiting for a permit counts toward the timeout
+        let deadline = tokio::time::Instant::now() + VERIFY_TIMEOUT;
+        let timed_out = || ZkpError::Timeout(format!("Verification exceeded {}s", VERIFY_TIMEOUT.as_secs()));
+        let _permit = tokio::time::timeout_at(deadline, self.verify_permits.acquire())
+            .await
+            .map_err(|_| timed_out())?
+            .map_err(|e| ZkpError::StateError(format!("Verifier pool closed: {}", e)))?;
+
+        let scratch = ScratchDir::create_async().await?;
+        let proof_path = scratch.0.join("proof");
+        let public_inputs_path = scratch.0.join("public_inputs");
+        let vk_path = scratch.0.join("vk");
+        scratch.write_private("proof", proof).await?;
+        scratch.write_private("public_inputs", public_inputs.concat()).await?;
+
+        let verify = async {
+            match (verification_key, &request.circuit_path) {
+                (Some(key), _) => scratch.write_private("vk", key).await?,
+                (None, Some(circuit_path)) => {
+                    Self::write_vk(&compiled_circuit(circuit_path)?, &scratch.0).await?;
+                }
+                (None, None) => unreachable!("checked above"),
+            }
+            Self::run_verifier(&vk_path, &proof_path, &public_inputs_path).await
+        };
+        let output = tokio::time::timeout_at(deadline, verify).await.map_err(|_| timed_out())??;
+
+        let reason = verifier_rejection(&output);
+        Ok(VerifyProofResponse {
+            valid: reason.is_none(),
+            reason,
+            verifier: "bb".to_string(),
+        })
+    }
+
+    /// A mock proof is valid when it is the proof `generate_mock_proof` makes for its task id and,
+    /// if public inputs are given, they are exactly its input commitment
+    fn verify_mock_proof(proof: &str, public_inputs: &[[u8; 32]]) -> VerifyProofResponse {
+        let check = || -> Result<(), String> {
+            let proof: serde_json::Value =
+                serde_json::from_str(proof).map_err(|e| format!("Not a mock proof: {}", e))?;
+            let field = |name: &str| proof.get(name).and_then(|value| value.as_str()).unwrap_or_default();
+            if field("proof") != format!("mock_proof_{}", field("task_id")) {
+                return Err("Mock proof does not match its task".to_string());
+            }
+            if !public_inputs.is_empty() {
+                let commitment = parse_field_element(field("input_commitment")).map_err(|e| e.to_string())?;
+                if public_inputs != [commitment] {
+                    return Err("Public inputs do not match the proof's input commitment".to_string());
+                }
+            }
+            Ok(())
+        };
+        let reason = check().err();
+        VerifyProofResponse {
+            valid: reason.is_none(),
+            reason,
+            verifier: "mock".to_string(),
+        }
+    }
+
+    /// Run a prover backend command to completion with its output captured. Dropping the
+    /// returned future (cancellation, timeout) kills the command and everything it spawned.
+    async fn run_backend(mut command: Command, name: &str) -> ZkpResult<std::process::Output> {
+        command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // Run in its own process group so helpers it spawns can be killed too
+        #[cfg(unix)]
+        command.process_group(0);
+
+        let child = command
+            .spawn()
+            .map_err(|e| ZkpError::NoirCommandError(format!("Failed to execute {}: {}", name, e)))?;
+        let mut group = ProcessGroupGuard(child.id());
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|e| ZkpError::NoirCommandError(format!("Failed to wait for {}: {}", name, e)))?;
+        group.disarm();
+        Ok(output)
+    }
+
+    async fn generate_mock_proof(task_id: &str, input_commitment: &[u8; 32]) -> ZkpResult<String> {
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
         
         let mock_proof = serde_json::json!({
             "task_id": task_id,
-            "input": input,
+            "input_commitment": format!("0x{}", hex::encode(input_commitment)),
             "proof": format!("mock_proof_{}", task_id),
             "timestamp": chrono::Utc::now().to_rfc3339(),
         });
@@ -225,25 +1225,166 @@ impl ZkpService {
         Ok(serde_json::to_string(&mock_proof)?)
     }
 
-    pub fn retrieve_output(&self, task_id: &str) -> ZkpResult<ProofResponse> {
-        let proofs = self.active_proofs.lock().unwrap();
-        let task = proofs
-            .get(task_id)
+    /// Sign a receipt over keccak256(abi.encode(keccak256(task_id), circuit_hash, input_commitment,
+    /// proof_hash, completed_at)). The signature is EIP-191 over that 32-byte digest, so contracts
+    /// can check it with `ECDSA.recover(MessageHashUtils.toEthSignedMessageHash(digest), signature)`.
+    ///
+    /// `circuit_hash` is keccak256 of the compiled circuit artifact. Mock proofs of a circuit that
+    /// has not been compiled carry a zero circuit hash instead; real proofs always hash the artifact.
+    fn build_receipt(
+        secp: &Secp256k1<secp256k1::All>,
+        keyring: &RwLock<KeyRing>,
+        task: &QueuedProofTask,
+        mock: bool,
+        input_commitment: &[u8; 32],
+        proof: &str,
+    ) -> ZkpResult<ProofReceipt> {
+        let circuit = compiled_circuit(&task.circuit_path).and_then(|path| Ok(std::fs::read(path)?));
+        let circuit_hash = match circuit {
+            Ok(circuit) => ethereum::keccak256(&circuit),
+            Err(_) if mock => [0u8; 32],
+            Err(e) => {
+                return Err(ZkpError::ProofGenerationError(format!(
+                    "Failed to read the compiled circuit for {}: {}",
+                    task.circuit_path, e
+                )))
+            }
+        };
+        let proof_hash = ethereum::keccak256(proof.as_bytes());
+        let completed_at = chrono::Utc::now().timestamp();
+
+        let mut encoded = Vec::with_capacity(5 * 32);
+        encoded.extend_from_slice(&ethereum::keccak256(task.task_id.as_bytes()));
+        encoded.extend_from_slice(&circuit_hash);
+        encoded.extend_from_slice(input_commitment);
+        encoded.extend_from_slice(&proof_hash);
+        let mut timestamp_word = [0u8; 32];
+        timestamp_word[24..].copy_from_slice(&(completed_at as u64).to_be_bytes());
+        encoded.extend_from_slice(&timestamp_word);
+        let digest = ethereum::keccak256(&encoded);
+
+        let keyring = keyring.read().unwrap();
+        let signature = ethereum::sign_personal_message(secp, keyring.active_key(), &digest)?;
+        let public_key = secp256k1::PublicKey::from_secret_key(secp, keyring.active_key());
+
+        Ok(ProofReceipt {
+            task_id: task.task_id.clone(),
+            circuit_hash: format!("0x{}", hex::encode(circuit_hash)),
+            input_commitment: format!("0x{}", hex::encode(input_commitment)),
+            proof_hash: format!("0x{}", hex::encode(proof_hash)),
+            completed_at,
+            digest: format!("0x{}", hex::encode(digest)),
+            signature,
+            signer: ethereum::format_address(&ethereum::address_from_public_key(&public_key)),
+            key_version: keyring.active_version(),
+        })
+    }
+
+    pub async fn retrieve_output(&self, task_id: &str) -> ZkpResult<ProofResponse> {
+        let queue = self.queue.clone();
+        let task_id = task_id.to_string();
+        task_store::run_blocking(&self.tasks, move |tasks| Self::task_response(tasks, &queue, &task_id)).await
+    }
+
+    /// Follow tasks as they change. The stream opens with the current state of each task, then
+    /// yields every status change and proving phase, and ends once all of them have finished.
+    pub async fn watch_tasks(&self, task_ids: &[String]) -> ZkpResult<impl Stream<Item = TaskEvent> + Send + use<>> {
+        if task_ids.is_empty() {
+            return Err(ZkpError::InvalidInput("At least one task id is required".to_string()));
+        }
+        // Subscribe before reading the snapshot so no change in between is missed
+        let mut watch = TaskWatch {
+            receiver: self.events.subscribe(),
+            tasks: self.tasks.clone(),
+            queue: self.queue.clone(),
+            unfinished: task_ids.iter().cloned().collect(),
+            backlog: VecDeque::new(),
+        };
+        let snapshot = watch.snapshot().await?;
+        watch.backlog.extend(snapshot);
+
+        Ok(stream::unfold(watch, |mut watch| async move {
+            loop {
+                if let Some(event) = watch.backlog.pop_front() {
+                    if !watch.unfinished.contains(&event.task.task_id) {
+                        continue;
+                    }
+                    if event.finished {
+                        watch.unfinished.remove(&event.task.task_id);
+                    }
+                    return Some((event, watch));
+                }
+                if watch.unfinished.is_empty() {
+                    return None;
+                }
+                match watch.receiver.recv().await {
+                    Ok(event) => watch.backlog.push_back(event),
+                    // Missed events; the current state of each task stands in for them
+                    Err(broadcast::error::RecvError::Lagged(_)) => match watch.snapshot().await {
+                        Ok(snapshot) => watch.backlog.extend(snapshot),
+                        Err(_) => return None,
+                    },
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }))
+    }
+
+    fn task_response(tasks: &dyn TaskStore, queue: &TaskQueue, task_id: &str) -> ZkpResult<ProofResponse> {
+        let task = tasks
+            .get(task_id)?
             .ok_or_else(|| ZkpError::InvalidInput(format!("Task {} not found", task_id)))?;
 
-        let (status, proof, error) = match &task.status {
-            ProofStatus::Pending => ("pending".to_string(), None, None),
-            ProofStatus::InProgress => ("in_progress".to_string(), None, None),
-            ProofStatus::Completed { proof } => ("completed".to_string(), Some(proof.clone()), None),
-            ProofStatus::Failed { error } => ("failed".to_string(), None, Some(error.clone())),
+        let mut response = Self::status_response(task_id, &task.status);
+        if let ProofStatus::Pending = task.status {
+            (response.queue_position, response.estimated_wait_ms) = Self::queue_estimate(queue, task_id);
+        }
+        response.webhook_attempts = task.webhook_attempts;
+        Ok(response)
+    }
+
+    /// Response fields that follow from the status alone (no queue estimates)
+    fn status_response(task_id: &str, status: &ProofStatus) -> ProofResponse {
+        let (proof, receipt, sealed_output, error) = match status {
+            ProofStatus::Completed { proof, receipt } => (Some(proof.clone()), Some(receipt.clone()), None, None),
+            ProofStatus::CompletedSealed { sealed_output } => (None, None, Some(sealed_output.clone()), None),
+            ProofStatus::Failed { error, .. } => (None, None, None, Some(error.clone())),
+            ProofStatus::Expired { expired_at } => (
+                None,
+                None,
+                None,
+                Some(format!("Task result expired at {} and is no longer retained", expired_at)),
+            ),
+            ProofStatus::Pending | ProofStatus::InProgress | ProofStatus::Cancelled => (None, None, None, None),
+        };
+        let error_code = match status {
+            ProofStatus::Failed { code, .. } => code.clone(),
+            ProofStatus::Expired { .. } => Some("expired".to_string()),
+            _ => None,
         };
 
-        Ok(ProofResponse {
+        ProofResponse {
             task_id: task_id.to_string(),
-            status,
+            status: status.name().to_string(),
             proof,
+            receipt,
+            sealed_output,
             error,
-        })
+            error_code,
+            queue_position: None,
+            estimated_wait_ms: None,
+            webhook_attempts: Vec::new(),
+            input_salt: None,
+        }
+    }
+
+    fn task_event(task: ProofResponse, phase: Option<ProofPhase>) -> TaskEvent {
+        TaskEvent {
+            finished: !matches!(task.status.as_str(), "pending" | "in_progress"),
+            task,
+            phase,
+            timestamp: chrono::Utc::now().timestamp(),
+        }
     }
 
     pub fn write_state(&self, key: String, value: String) -> ZkpResult<()> {
@@ -268,10 +1409,35 @@ impl ZkpService {
         Ok(submission_id)
     }
 
-    pub fn decrypt_input(&self, encrypted_data: &str) -> ZkpResult<String> {
-        let decoded = hex::decode(encrypted_data.trim_start_matches("0x"))
+    /// Decrypt a payload addressed to a service key version (default: the active one) in the
+    /// given suite and return it as UTF-8 text or, with `encoding` "hex", as 0x-prefixed hex.
+    /// Proof input envelopes are bound to `PROOF_INPUT_CONTEXT` and cannot be opened here.
+    pub fn decrypt_input(
+        &self,
+        encrypted_data: &str,
+        suite: Option<&str>,
+        key_version: Option<u32>,
+        encoding: Option<&str>,
+    ) -> ZkpResult<(String, String)> {
+        let suite = EncryptionSuite::from_id(suite)?;
+        let payload = hex::decode(encrypted_data.trim_start_matches("0x"))
             .map_err(|e| ZkpError::DecryptionError(format!("Failed to decode hex: {}", e)))?;
-        Ok(hex::encode(&decoded))
+        let key = {
+            let keyring = self.keyring.read().unwrap();
+            *keyring.key(key_version.unwrap_or_else(|| keyring.active_version()))?
+        };
+        let plaintext = suite.decrypt(&key, &payload, b"")?;
+
+        match encoding.unwrap_or("utf8") {
+            "utf8" => {
+                let text = String::from_utf8(plaintext).map_err(|_| {
+                    ZkpError::DecryptionError("Decrypted data is not valid UTF-8; request hex encoding".to_string())
+                })?;
+                Ok((text, "utf8".to_string()))
+            }
+            "hex" => Ok((format!("0x{}", hex::encode(plaintext)), "hex".to_string())),
+            other => Err(ZkpError::InvalidInput(format!("Unsupported output encoding: {}", other))),
+        }
     }
 
     pub async fn git_clone(&self, gitrepo: &str) -> ZkpResult<String> {
@@ -314,7 +1480,7 @@ impl ZkpService {
                 .clone()
         };
 
-        let path = std::path::Path::new(&dir_path);
+        let path = Path::new(&dir_path);
         
         if !path.exists() {
             let mut dirs = self.tracked_directories.lock().unwrap();
@@ -335,8 +1501,7 @@ impl ZkpService {
         tokio::fs::remove_dir_all(path)
             .await
             .map_err(|e| {
-                ZkpError::IoError(std::io::Error::new(
-                    std::io::ErrorKind::Other,
+                ZkpError::IoError(std::io::Error::other(
                     format!("Failed to delete directory {}: {}", dir_path, e),
                 ))
             })?;
@@ -350,7 +1515,7 @@ impl ZkpService {
     }
 
     pub async fn delete_directory(&self, dir_path: &str) -> ZkpResult<()> {
-        let path = std::path::Path::new(dir_path);
+        let path = Path::new(dir_path);
         
         if !path.exists() {
             return Err(ZkpError::IoError(std::io::Error::new(
@@ -369,8 +1534,7 @@ impl ZkpService {
         tokio::fs::remove_dir_all(path)
             .await
             .map_err(|e| {
-                ZkpError::IoError(std::io::Error::new(
-                    std::io::ErrorKind::Other,
+                ZkpError::IoError(std::io::Error::other(
                     format!("Failed to delete directory {}: {}", dir_path, e),
                 ))
             })?;
@@ -388,3 +1552,489 @@ impl ZkpService {
         dirs.keys().cloned().collect()
     }
 }
+
+/// Kills a child's whole process group when dropped, unless the child exited normally
+struct ProcessGroupGuard(Option<u32>);
+
+impl ProcessGroupGuard {
+    fn disarm(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pgid) = self.0 {
+            // SAFETY: killpg has no memory-safety preconditions; the group id is the child's pid,
+            // which led the group since spawn and has not been reaped yet
+            unsafe {
+                libc::killpg(pgid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+/// State of one `watch_tasks` stream
+struct TaskWatch {
+    receiver: broadcast::Receiver<TaskEvent>,
+    tasks: Arc<dyn TaskStore>,
+    queue: Arc<TaskQueue>,
+    unfinished: HashSet<String>,
+    backlog: VecDeque<TaskEvent>,
+}
+
+impl TaskWatch {
+    async fn snapshot(&self) -> ZkpResult<Vec<TaskEvent>> {
+        let queue = self.queue.clone();
+        let task_ids: Vec<String> = self.unfinished.iter().cloned().collect();
+        task_store::run_blocking(&self.tasks, move |tasks| {
+            task_ids
+                .iter()
+                .map(|task_id| {
+                    let response = ZkpService::task_response(tasks, &queue, task_id)?;
+                    Ok(ZkpService::task_event(response, None))
+                })
+                .collect()
+        })
+        .await
+    }
+}
+
+/// Private working directory for backend input files, removed when dropped
+struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    fn create() -> ZkpResult<Self> {
+        let path = std::env::temp_dir().join(format!("zkpruntime-{}", Uuid::new_v4().simple()));
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder.create(&path)?;
+        Ok(Self(path))
+    }
+
+    async fn create_async() -> ZkpResult<Self> {
+        tokio::task::spawn_blocking(Self::create)
+            .await
+            .map_err(|e| ZkpError::StateError(format!("Failed to create scratch directory: {}", e)))?
+    }
+
+    /// Write a file only the service user can read
+    async fn write_private(&self, name: &str, contents: Vec<u8>) -> ZkpResult<()> {
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(self.0.join(name)).await?;
+        file.write_all(&contents).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The compiled circuit artifact for a circuit path: the path itself when it is a file, or the
+/// single `target/*.json` artifact `nargo compile` leaves in a Noir project directory
+fn compiled_circuit(circuit_path: &str) -> ZkpResult<PathBuf> {
+    let path = Path::new(circuit_path);
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+    let mut artifacts = Vec::new();
+    for entry in std::fs::read_dir(path.join("target"))? {
+        let artifact = entry?.path();
+        if artifact.extension().and_then(|e| e.to_str()) == Some("json") {
+            artifacts.push(artifact);
+        }
+    }
+    match <[_; 1]>::try_from(artifacts) {
+        Ok([artifact]) => Ok(artifact),
+        Err(artifacts) => Err(ZkpError::InvalidInput(format!(
+            "Expected one compiled circuit in {}/target, found {}",
+            circuit_path,
+            artifacts.len()
+        ))),
+    }
+}
+
+// bb exits non-zero for proofs that do not verify; its stderr says why
+fn verifier_rejection(output: &std::process::Output) -> Option<String> {
+    (!output.status.success()).then(|| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.trim();
+        if reason.is_empty() {
+            format!("Verifier exited with {}", output.status)
+        } else {
+            reason.to_string()
+        }
+    })
+}
+
+/// A public input as a 32-byte big-endian field element: 0x-hex (up to 32 bytes) or decimal
+fn parse_field_element(value: &str) -> ZkpResult<[u8; 32]> {
+    ethereum::parse_uint256(&serde_json::Value::String(value.to_string()))
+        .map_err(|e| ZkpError::InvalidInput(format!("Invalid public input {}: {}", value, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keystore::tests::TempDir;
+    use crate::task_store::TaskUpdate;
+    use crate::types::BatchRef;
+
+    fn open_keyring(dir: &TempDir) -> RwLock<KeyRing> {
+        RwLock::new(KeyRing::open(&dir.0.join("service-key.json"), "passphrase").unwrap())
+    }
+
+    fn queued_task(circuit_path: &str) -> QueuedProofTask {
+        QueuedProofTask {
+            task_id: "proof_test".to_string(),
+            circuit_path: circuit_path.to_string(),
+            input: TaskInput {
+                suite: EncryptionSuite::Secp256k1Ecies,
+                key_version: None,
+                payload: Vec::new(),
+                sealed_salt: Vec::new(),
+            },
+            mock_mode: false,
+            recipient: None,
+            timeout_secs: None,
+            priority: Default::default(),
+        }
+    }
+
+    fn batch_task(status: ProofStatus, index: usize) -> ProofTask {
+        ProofTask {
+            status,
+            submitted_at: 0,
+            queued: None,
+            finished_at: None,
+            callback_url: None,
+            webhook_attempts: Vec::new(),
+            batch: Some(BatchRef {
+                batch_id: "batch".to_string(),
+                index,
+            }),
+        }
+    }
+
+    // Memory store that refuses to write one task id
+    struct FailingStore {
+        inner: MemoryTaskStore,
+        fail_on: &'static str,
+    }
+
+    impl TaskStore for FailingStore {
+        fn put(&self, task_id: &str, task: &ProofTask) -> ZkpResult<()> {
+            if task_id == self.fail_on {
+                return Err(ZkpError::StateError("Disk full".to_string()));
+            }
+            self.inner.put(task_id, task)
+        }
+
+        fn get(&self, task_id: &str) -> ZkpResult<Option<ProofTask>> {
+            self.inner.get(task_id)
+        }
+
+        fn list(&self) -> ZkpResult<Vec<(String, ProofTask)>> {
+            self.inner.list()
+        }
+
+        fn remove(&self, task_id: &str) -> ZkpResult<()> {
+            self.inner.remove(task_id)
+        }
+
+        fn update(&self, task_id: &str, update: TaskUpdate<'_>) -> ZkpResult<Option<ProofTask>> {
+            self.inner.update(task_id, update)
+        }
+    }
+
+    #[test]
+    fn failed_batch_write_leaves_no_tasks() {
+        let store = FailingStore {
+            inner: MemoryTaskStore::default(),
+            fail_on: "c",
+        };
+        let batch: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .enumerate()
+            .map(|(index, id)| (id.to_string(), batch_task(ProofStatus::Pending, index)))
+            .collect();
+        assert!(ZkpService::put_all(&store, &batch).is_err());
+        assert!(store.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn batches_are_pruned_once_all_tasks_are_evicted() {
+        let store = MemoryTaskStore::default();
+        store.put("a", &batch_task(ProofStatus::Expired { expired_at: 1 }, 0)).unwrap();
+        store.put("b", &batch_task(ProofStatus::Cancelled, 1)).unwrap();
+        let batches = Mutex::new(ZkpService::index_batches(&store.list().unwrap()));
+
+        // "b" is still retained
+        ZkpService::prune_batches(&store, &batches).unwrap();
+        assert!(batches.lock().unwrap().contains_key("batch"));
+
+        // "b" gone, "a" only an expired marker
+        store.remove("b").unwrap();
+        ZkpService::prune_batches(&store, &batches).unwrap();
+        assert!(batches.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn receipt_digest_recovers_to_signer() {
+        let dir = TempDir::new();
+        let keyring = open_keyring(&dir);
+        let secp = Secp256k1::new();
+        let circuit_path = dir.0.join("circuit.json");
+        std::fs::write(&circuit_path, b"{\"bytecode\":\"\"}").unwrap();
+        let task = queued_task(circuit_path.to_str().unwrap());
+        let commitment = [7u8; 32];
+        let receipt = ZkpService::build_receipt(&secp, &keyring, &task, false, &commitment, "proof").unwrap();
+
+        // abi.encode(keccak256(task_id), circuit_hash, input_commitment, proof_hash, completed_at)
+        let mut encoded = ethereum::keccak256(b"proof_test").to_vec();
+        encoded.extend_from_slice(&ethereum::keccak256(b"{\"bytecode\":\"\"}"));
+        encoded.extend_from_slice(&commitment);
+        encoded.extend_from_slice(&ethereum::keccak256(b"proof"));
+        encoded.extend_from_slice(&[0u8; 24]);
+        encoded.extend_from_slice(&(receipt.completed_at as u64).to_be_bytes());
+        let digest = ethereum::keccak256(&encoded);
+        assert_eq!(receipt.digest, format!("0x{}", hex::encode(digest)));
+
+        let signature = ethereum::decode_hex_bytes(&receipt.signature).unwrap();
+        let signer =
+            ethereum::recover_public_key(&secp, &ethereum::personal_message_hash(&digest), &signature).unwrap();
+        assert_eq!(receipt.signer, ethereum::format_address(&ethereum::address_from_public_key(&signer)));
+        assert_eq!(signer, secp256k1::PublicKey::from_secret_key(&secp, keyring.read().unwrap().active_key()));
+    }
+
+    #[test]
+    fn receipt_hashes_the_compiled_circuit() {
+        let dir = TempDir::new();
+        let keyring = open_keyring(&dir);
+        let secp = Secp256k1::new();
+        let project = dir.0.join("circuit");
+        std::fs::create_dir_all(project.join("target")).unwrap();
+        let project_task = queued_task(project.to_str().unwrap());
+
+        // A project that has not been compiled only gets a (zero) circuit hash for mock proofs
+        assert!(ZkpService::build_receipt(&secp, &keyring, &project_task, false, &[0; 32], "proof").is_err());
+        let receipt = ZkpService::build_receipt(&secp, &keyring, &project_task, true, &[0; 32], "proof").unwrap();
+        assert_eq!(receipt.circuit_hash, format!("0x{}", hex::encode([0u8; 32])));
+
+        std::fs::write(project.join("target/circuit.json"), b"artifact").unwrap();
+        let receipt = ZkpService::build_receipt(&secp, &keyring, &project_task, false, &[0; 32], "proof").unwrap();
+        assert_eq!(receipt.circuit_hash, format!("0x{}", hex::encode(ethereum::keccak256(b"artifact"))));
+    }
+
+    #[test]
+    fn input_commitment_is_salted() {
+        let dir = TempDir::new();
+        let keyring = open_keyring(&dir);
+        let secp = Secp256k1::new();
+        let public_key = secp256k1::PublicKey::from_secret_key(&secp, keyring.read().unwrap().active_key());
+        let plaintext = br#"{"age":"42"}"#;
+        let seal = |salt: &[u8]| TaskInput {
+            suite: EncryptionSuite::Secp256k1Ecies,
+            key_version: None,
+            payload: ecies::encrypt(&public_key, plaintext, PROOF_INPUT_CONTEXT).unwrap(),
+            sealed_salt: ecies::encrypt(&public_key, salt, INPUT_SALT_CONTEXT).unwrap(),
+        };
+
+        let (input, commitment) = ZkpService::open_input(&keyring, &seal(&[1; 32])).unwrap();
+        assert_eq!(input, serde_json::json!({ "age": "42" }));
+        assert_ne!(commitment, ethereum::keccak256(plaintext));
+        assert_eq!(commitment, ethereum::keccak256(&[&[1u8; 32][..], plaintext].concat()));
+
+        let (_, other) = ZkpService::open_input(&keyring, &seal(&[2; 32])).unwrap();
+        assert_ne!(commitment, other);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn scratch_files_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let scratch = ScratchDir::create_async().await.unwrap();
+        scratch.write_private("Prover.toml", b"x = \"1\"\n".to_vec()).await.unwrap();
+        let mode = |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&scratch.0), 0o700);
+        assert_eq!(mode(&scratch.0.join("Prover.toml")), 0o600);
+
+        let path = scratch.0.clone();
+        drop(scratch);
+        assert!(!path.exists());
+    }
+
+    fn finished_task(status: ProofStatus, finished_at: i64) -> ProofTask {
+        ProofTask {
+            status,
+            submitted_at: finished_at,
+            queued: None,
+            finished_at: Some(finished_at),
+            callback_url: None,
+            webhook_attempts: Vec::new(),
+            batch: None,
+        }
+    }
+
+    #[test]
+    fn sweep_expires_by_age_count_and_size() {
+        let now = chrono::Utc::now().timestamp();
+        let store = MemoryTaskStore::default();
+        store.put("old", &finished_task(ProofStatus::Cancelled, now - 1000)).unwrap();
+        for (task_id, age) in [("a", 10), ("b", 20), ("c", 30)] {
+            store.put(task_id, &finished_task(ProofStatus::Cancelled, now - age)).unwrap();
+        }
+        store.put("pending", &finished_task(ProofStatus::Pending, now - 1000)).unwrap();
+        store.put("tombstone_old", &finished_task(ProofStatus::Expired { expired_at: now - 1000 }, now - 2000)).unwrap();
+        store.put("tombstone_new", &finished_task(ProofStatus::Expired { expired_at: now - 10 }, now - 2000)).unwrap();
+        let status = |task_id: &str| store.get(task_id).unwrap().map(|task| task.status.name().to_string());
+
+        // "old" is past the age limit and "c" the oldest beyond the count limit; unfinished tasks
+        // are never expired and only old tombstones are dropped
+        let mut retention = RetentionPolicy {
+            max_age_secs: 500,
+            max_count: Some(2),
+            max_bytes: None,
+            tombstone_secs: 100,
+            sweep_interval_secs: 60,
+        };
+        assert_eq!(ZkpService::sweep_tasks(&store, &retention).unwrap(), 2);
+        assert_eq!(status("old").as_deref(), Some("expired"));
+        assert_eq!(status("a").as_deref(), Some("cancelled"));
+        assert_eq!(status("b").as_deref(), Some("cancelled"));
+        assert_eq!(status("c").as_deref(), Some("expired"));
+        assert_eq!(status("pending").as_deref(), Some("pending"));
+        assert_eq!(status("tombstone_old"), None);
+        assert_eq!(status("tombstone_new").as_deref(), Some("expired"));
+
+        // Room for only the newest task
+        let size = |task_id: &str| serde_json::to_vec(&store.get(task_id).unwrap().unwrap()).unwrap().len() as u64;
+        retention.max_count = None;
+        retention.max_bytes = Some(size("a") + size("b") - 1);
+        assert_eq!(ZkpService::sweep_tasks(&store, &retention).unwrap(), 1);
+        assert_eq!(status("a").as_deref(), Some("cancelled"));
+        assert_eq!(status("b").as_deref(), Some("expired"));
+
+        let queue = TaskQueue::new(10, 1, Duration::from_secs(60));
+        let response = ZkpService::task_response(&store, &queue, "c").unwrap();
+        assert_eq!(response.status, "expired");
+        assert_eq!(response.error_code.as_deref(), Some("expired"));
+
+        // A large task past the size limit takes the smaller, older ones with it
+        let store = MemoryTaskStore::default();
+        let large = ProofStatus::Failed {
+            error: "x".repeat(4096),
+            code: None,
+        };
+        store.put("small_new", &finished_task(ProofStatus::Cancelled, now - 10)).unwrap();
+        store.put("large", &finished_task(large, now - 20)).unwrap();
+        store.put("small_old", &finished_task(ProofStatus::Cancelled, now - 30)).unwrap();
+        let size = |task_id: &str| serde_json::to_vec(&store.get(task_id).unwrap().unwrap()).unwrap().len() as u64;
+        let status = |task_id: &str| store.get(task_id).unwrap().map(|task| task.status.name().to_string());
+        retention.max_bytes = Some(size("small_new") + size("small_old"));
+        assert_eq!(ZkpService::sweep_tasks(&store, &retention).unwrap(), 2);
+        assert_eq!(status("small_new").as_deref(), Some("cancelled"));
+        assert_eq!(status("large").as_deref(), Some("expired"));
+        assert_eq!(status("small_old").as_deref(), Some("expired"));
+    }
+
+    #[tokio::test]
+    async fn timed_out_proofs_fail_without_a_duration_sample() {
+        let queue = TaskQueue::new(10, 1, Duration::from_secs(60));
+        let stalled = async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(ProofStatus::Cancelled)
+        };
+        match ZkpService::prove_within(&queue, Duration::from_millis(10), stalled).await {
+            ProofStatus::Failed { code, .. } => assert_eq!(code.as_deref(), Some("timeout")),
+            other => panic!("expected a failure, got {}", other.name()),
+        }
+        assert_eq!(queue.estimated_wait(1), None);
+
+        let failing = async { Err(ZkpError::ProofGenerationError("nargo failed".to_string())) };
+        ZkpService::prove_within(&queue, Duration::from_secs(1), failing).await;
+        assert_eq!(queue.estimated_wait(1), None);
+
+        ZkpService::prove_within(&queue, Duration::from_secs(1), async { Ok(ProofStatus::Cancelled) }).await;
+        assert!(queue.estimated_wait(1).is_some());
+    }
+
+    #[tokio::test]
+    async fn mock_proofs_verify_against_their_task_and_commitment() {
+        let commitment = ethereum::keccak256(b"input");
+        let proof = ZkpService::generate_mock_proof("proof_a", &commitment).await.unwrap();
+        let verified = ZkpService::verify_mock_proof(&proof, &[commitment]);
+        assert!(verified.valid, "{:?}", verified.reason);
+        assert!(ZkpService::verify_mock_proof(&proof, &[]).valid);
+
+        let other = ethereum::keccak256(b"other input");
+        let mismatched = ZkpService::verify_mock_proof(&proof, &[other]);
+        assert!(!mismatched.valid);
+        assert!(!ZkpService::verify_mock_proof(&proof, &[commitment, commitment]).valid);
+
+        // Relabelling a proof for another task breaks it
+        let mut forged: serde_json::Value = serde_json::from_str(&proof).unwrap();
+        forged["task_id"] = "proof_b".into();
+        let forged = ZkpService::verify_mock_proof(&forged.to_string(), &[commitment]);
+        assert!(!forged.valid);
+        assert_eq!(forged.reason.as_deref(), Some("Mock proof does not match its task"));
+
+        assert!(!ZkpService::verify_mock_proof("not a proof", &[]).valid);
+    }
+
+    #[tokio::test]
+    async fn only_mock_services_accept_mock_requests() {
+        let request = || -> ProofRequest {
+            serde_json::from_value(serde_json::json!({
+                "circuit_path": "circuit",
+                "input": { "x": "1" },
+                "mock": true,
+            }))
+            .unwrap()
+        };
+
+        let dir = TempDir::new();
+        let mut config = crate::backup::tests::test_config(&dir.0);
+        config.mock_mode = false;
+        let service = ZkpService::new(&config).unwrap();
+        assert!(matches!(service.execute_zkp(request()).await, Err(ZkpError::InvalidInput(_))));
+        // A hand-written mock proof is not vouched for either
+        let verify_request = || -> VerifyProofRequest {
+            serde_json::from_value(serde_json::json!({
+                "proof": r#"{"task_id":"proof_x","proof":"mock_proof_proof_x"}"#,
+                "public_inputs": [],
+                "circuit_path": "circuit",
+                "mock": true,
+            }))
+            .unwrap()
+        };
+        assert!(matches!(service.verify_proof(verify_request()).await, Err(ZkpError::InvalidInput(_))));
+
+        let dir = TempDir::new();
+        let service = ZkpService::new(&crate::backup::tests::test_config(&dir.0)).unwrap();
+        assert!(service.execute_zkp(request()).await.is_ok());
+        assert!(service.verify_proof(verify_request()).await.unwrap().valid);
+    }
+
+    #[tokio::test]
+    async fn startup_restore_needs_the_expected_public_key() {
+        let dir = TempDir::new();
+        let mut config = crate::backup::tests::test_config(&dir.0);
+        config.key_restore_shares = Some(dir.0.join("shares.json"));
+        assert!(matches!(ZkpService::new(&config), Err(ZkpError::KeystoreError(_))));
+        assert!(!config.keystore_path.exists());
+    }
+}
@@ -0,0 +1,266 @@
+//! Encrypted session channel negotiated with an ECDH handshake.
+//!
+//! The client sends an ephemeral secp256k1 public key and the service answers with its own
+//! ephemeral key (signed by the service key) and a session id. Both sides run HKDF-SHA256 over
+//! the ECDH shared x coordinate, with the session id as salt and
+//! `"zkpruntime session v1" ‖ client key ‖ server key` (compressed) as info, to get 64 bytes:
+//! the first 32 are the AES-256-GCM key for requests, the last 32 the key for responses.
+//!
+//! Every request carries a sequence number that must be greater than the last one accepted in
+//! the session. The nonce is four zero bytes followed by the big-endian u64 sequence number, and
+//! the response reuses the request's number under the response key.
+//! Request AAD is `session id ‖ seq ‖ method ‖ " " ‖ path and query`; response AAD is
+//! `session id ‖ seq ‖ status code (u16)`. Requests without a body (such as GETs) encrypt the
+//! empty plaintext, so their ciphertext is just the 16-byte tag authenticating method and path.
+//!
+//! At most `max_sessions` sessions are live at once; handshakes beyond that are refused until
+//! one expires.
+
+use crate::errors::{ZkpError, ZkpResult};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use hkdf::Hkdf;
+use secp256k1::{ecdh, PublicKey, Secp256k1};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+const KEY_DERIVATION_INFO: &[u8] = b"zkpruntime session v1";
+
+struct Session {
+    request_key: Aes256Gcm,
+    response_key: Aes256Gcm,
+    last_seq: Option<u64>,
+    expires_at: i64,
+}
+
+pub struct Handshake {
+    pub session_id: String,
+    pub server_public_key: PublicKey,
+    pub expires_at: i64,
+}
+
+pub struct SessionManager {
+    ttl_secs: i64,
+    max_sessions: usize,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl SessionManager {
+    pub fn new(ttl_secs: u64, max_sessions: usize) -> Self {
+        Self {
+            ttl_secs: ttl_secs as i64,
+            max_sessions,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Forget expired sessions
+    pub fn prune(&self) {
+        let now = chrono::Utc::now().timestamp();
+        self.sessions.lock().unwrap().retain(|_, session| session.expires_at > now);
+    }
+
+    /// Complete the server side of a handshake and register the new session
+    pub fn open(&self, client_public_key: &PublicKey) -> ZkpResult<Handshake> {
+        let secp = Secp256k1::signing_only();
+        let (server_secret, server_public_key) = secp.generate_keypair(&mut rand::thread_rng());
+        let session_id = format!("session_{}", uuid::Uuid::new_v4().simple());
+
+        let shared_point = ecdh::shared_secret_point(client_public_key, &server_secret);
+        let keys = session_keys(&shared_point, &session_id, client_public_key, &server_public_key)?;
+
+        let now = chrono::Utc::now().timestamp();
+        let expires_at = now + self.ttl_secs;
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.expires_at > now);
+        if sessions.len() >= self.max_sessions {
+            let next_expiry = sessions.values().map(|session| session.expires_at).min().unwrap_or(now);
+            return Err(ZkpError::SessionLimit {
+                retry_after_secs: (next_expiry - now).max(1) as u64,
+            });
+        }
+        sessions.insert(
+            session_id.clone(),
+            Session {
+                request_key: Aes256Gcm::new_from_slice(&keys[..32]).expect("32-byte key"),
+                response_key: Aes256Gcm::new_from_slice(&keys[32..]).expect("32-byte key"),
+                last_seq: None,
+                expires_at,
+            },
+        );
+
+        Ok(Handshake {
+            session_id,
+            server_public_key,
+            expires_at,
+        })
+    }
+
+    /// Authenticate and decrypt a request body, rejecting expired sessions and replayed or
+    /// out-of-order sequence numbers
+    pub fn decrypt_request(
+        &self,
+        session_id: &str,
+        seq: u64,
+        method: &str,
+        path: &str,
+        ciphertext: &[u8],
+    ) -> ZkpResult<Vec<u8>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| ZkpError::SessionError(format!("Unknown session {}", session_id)))?;
+        if session.expires_at <= chrono::Utc::now().timestamp() {
+            sessions.remove(session_id);
+            return Err(ZkpError::SessionError(format!("Session {} has expired", session_id)));
+        }
+        if session.last_seq.is_some_and(|last| seq <= last) {
+            return Err(ZkpError::SessionError(format!("Replayed or out-of-order sequence number {}", seq)));
+        }
+
+        let mut aad = aad_prefix(session_id, seq);
+        aad.extend_from_slice(format!("{} {}", method, path).as_bytes());
+        let plaintext = session
+            .request_key
+            .decrypt(&nonce(seq), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| ZkpError::SessionError("Request authentication failed".to_string()))?;
+        session.last_seq = Some(seq);
+        Ok(plaintext)
+    }
+
+    /// Encrypt the response to an accepted request
+    pub fn encrypt_response(&self, session_id: &str, seq: u64, status: u16, plaintext: &[u8]) -> ZkpResult<Vec<u8>> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get(session_id)
+            .ok_or_else(|| ZkpError::SessionError(format!("Unknown session {}", session_id)))?;
+
+        let mut aad = aad_prefix(session_id, seq);
+        aad.extend_from_slice(&status.to_be_bytes());
+        session
+            .response_key
+            .encrypt(&nonce(seq), Payload { msg: plaintext, aad: &aad })
+            .map_err(|_| ZkpError::EncryptionError("Response encryption failed".to_string()))
+    }
+}
+
+// Request key ‖ response key
+fn session_keys(
+    shared_point: &[u8; 64],
+    session_id: &str,
+    client_public_key: &PublicKey,
+    server_public_key: &PublicKey,
+) -> ZkpResult<[u8; 64]> {
+    let mut info = KEY_DERIVATION_INFO.to_vec();
+    info.extend_from_slice(&client_public_key.serialize());
+    info.extend_from_slice(&server_public_key.serialize());
+    let mut keys = [0u8; 64];
+    Hkdf::<Sha256>::new(Some(session_id.as_bytes()), &shared_point[..32])
+        .expand(&info, &mut keys)
+        .map_err(|e| ZkpError::KeyGenerationError(format!("HKDF expansion failed: {}", e)))?;
+    Ok(keys)
+}
+
+fn aad_prefix(session_id: &str, seq: u64) -> Vec<u8> {
+    let mut aad = session_id.as_bytes().to_vec();
+    aad.extend_from_slice(&seq.to_be_bytes());
+    aad
+}
+
+fn nonce(seq: u64) -> Nonce<aes_gcm::aead::consts::U12> {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&seq.to_be_bytes());
+    nonce.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The client's half of the handshake: (session, request key, response key)
+    fn handshake(manager: &SessionManager) -> (String, Aes256Gcm, Aes256Gcm) {
+        let secp = Secp256k1::new();
+        let (client_secret, client_public) = secp.generate_keypair(&mut rand::thread_rng());
+        let handshake = manager.open(&client_public).unwrap();
+        let shared_point = ecdh::shared_secret_point(&handshake.server_public_key, &client_secret);
+        let keys = session_keys(&shared_point, &handshake.session_id, &client_public, &handshake.server_public_key)
+            .unwrap();
+        (
+            handshake.session_id,
+            Aes256Gcm::new_from_slice(&keys[..32]).unwrap(),
+            Aes256Gcm::new_from_slice(&keys[32..]).unwrap(),
+        )
+    }
+
+    fn seal_request(session_id: &str, key: &Aes256Gcm, seq: u64, path: &str, body: &[u8]) -> Vec<u8> {
+        let mut aad = aad_prefix(session_id, seq);
+        aad.extend_from_slice(format!("POST {}", path).as_bytes());
+        key.encrypt(&nonce(seq), Payload { msg: body, aad: &aad }).unwrap()
+    }
+
+    #[test]
+    fn client_and_server_agree_on_keys() {
+        let manager = SessionManager::new(60, 10);
+        let (session_id, request_key, response_key) = handshake(&manager);
+
+        let request = seal_request(&session_id, &request_key, 1, "/write-state", b"{}");
+        let plaintext = manager.decrypt_request(&session_id, 1, "POST", "/write-state", &request).unwrap();
+        assert_eq!(plaintext, b"{}");
+        // Method and path are authenticated
+        let request = seal_request(&session_id, &request_key, 2, "/write-state", b"{}");
+        assert!(manager.decrypt_request(&session_id, 2, "POST", "/consult-x", &request).is_err());
+
+        let response = manager.encrypt_response(&session_id, 1, 200, b"ok").unwrap();
+        let mut aad = aad_prefix(&session_id, 1);
+        aad.extend_from_slice(&200u16.to_be_bytes());
+        let opened = response_key.decrypt(&nonce(1), Payload { msg: &response, aad: &aad }).unwrap();
+        assert_eq!(opened, b"ok");
+    }
+
+    #[test]
+    fn replayed_and_out_of_order_requests_are_rejected() {
+        let manager = SessionManager::new(60, 10);
+        let (session_id, request_key, _) = handshake(&manager);
+        let decrypt = |seq: u64| {
+            let request = seal_request(&session_id, &request_key, seq, "/", b"");
+            manager.decrypt_request(&session_id, seq, "POST", "/", &request)
+        };
+
+        assert!(decrypt(5).is_ok());
+        assert!(decrypt(5).is_err());
+        assert!(decrypt(4).is_err());
+        assert!(decrypt(7).is_ok());
+    }
+
+    #[test]
+    fn expired_sessions_are_rejected() {
+        let manager = SessionManager::new(0, 10);
+        let (session_id, request_key, _) = handshake(&manager);
+        let request = seal_request(&session_id, &request_key, 1, "/", b"");
+        let error = manager.decrypt_request(&session_id, 1, "POST", "/", &request).unwrap_err();
+        assert!(error.to_string().contains("expired"));
+        // and forgotten
+        let error = manager.decrypt_request(&session_id, 1, "POST", "/", &request).unwrap_err();
+        assert!(error.to_string().contains("Unknown session"));
+    }
+
+    #[test]
+    fn live_sessions_are_capped() {
+        let manager = SessionManager::new(60, 2);
+        handshake(&manager);
+        handshake(&manager);
+        let (_, client_public) = Secp256k1::new().generate_keypair(&mut rand::thread_rng());
+        match manager.open(&client_public) {
+            Err(ZkpError::SessionLimit { retry_after_secs }) => assert!((1..=60).contains(&retry_after_secs)),
+            _ => panic!("expected the session limit"),
+        }
+
+        // Expired sessions give their slots back, on the next handshake or when pruned
+        let manager = SessionManager::new(0, 1);
+        handshake(&manager);
+        handshake(&manager);
+        manager.prune();
+        assert!(manager.sessions.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+//! Shamir secret sharing over GF(256) for backing up the service key.
+//!
+//! Each byte of the secret is shared with its own random polynomial of degree `threshold - 1`;
+//! share `i` holds the evaluations at x = i. Every share is tagged with the public key of the
+//! secret it belongs to so a restore can be checked before the key is used.
+
+use crate::errors::{ZkpError, ZkpResult};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+// Version 1 shares held only the service key; version 2 shares hold a full key backup (see backup.rs)
+const SHARE_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyShare {
+    pub version: u32,
+    pub index: u8,
+    pub threshold: u8,
+    pub shares: u8,
+    pub public_key: String,
+    pub share: String,
+}
+
+pub fn split(secret: &[u8], threshold: u8, shares: u8, public_key: &str) -> ZkpResult<Vec<KeyShare>> {
+    if threshold < 2 || shares < threshold {
+        return Err(ZkpError::InvalidInput(format!(
+            "Threshold must be at least 2 and at most the number of shares (got {} of {})",
+            threshold, shares
+        )));
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut outputs = vec![Vec::with_capacity(secret.len()); shares as usize];
+    let mut coefficients = vec![0u8; threshold as usize];
+    for &byte in secret {
+        coefficients[0] = byte;
+        rng.fill_bytes(&mut coefficients[1..]);
+        for (i, output) in outputs.iter_mut().enumerate() {
+            output.push(evaluate(&coefficients, i as u8 + 1));
+        }
+    }
+
+    Ok(outputs
+        .into_iter()
+        .enumerate()
+        .map(|(i, data)| KeyShare {
+            version: SHARE_VERSION,
+            index: i as u8 + 1,
+            threshold,
+            shares,
+            public_key: public_key.to_string(),
+            share: format!("0x{}", hex::encode(data)),
+        })
+        .collect())
+}
+
+/// Rebuild the secret from at least `threshold` shares of the same split
+pub fn combine(shares: &[KeyShare]) -> ZkpResult<Vec<u8>> {
+    let first = shares
+        .first()
+        .ok_or_else(|| ZkpError::InvalidInput("No key shares provided".to_string()))?;
+
+    let mut points = Vec::with_capacity(shares.len());
+    for share in shares {
+        if share.version != SHARE_VERSION {
+            return Err(ZkpError::InvalidInput(format!(
+                "Key share {} has format version {}, expected {}; create new shares with split-key",
+                share.index, share.version, SHARE_VERSION
+            )));
+        }
+        if share.threshold != first.threshold || share.public_key != first.public_key {
+            return Err(ZkpError::InvalidInput(format!(
+                "Key share {} does not belong to the same split",
+                share.index
+            )));
+        }
+        if share.index == 0 || points.iter().any(|(x, _)| *x == share.index) {
+            return Err(ZkpError::InvalidInput(format!("Invalid or duplicate key share index {}", share.index)));
+        }
+        let data = hex::decode(share.share.trim_start_matches("0x"))
+            .map_err(|e| ZkpError::InvalidInput(format!("Invalid key share {}: {}", share.index, e)))?;
+        points.push((share.index, data));
+    }
+
+    if points.len() < first.threshold as usize {
+        return Err(ZkpError::InvalidInput(format!(
+            "{} key shares provided, {} required",
+            points.len(),
+            first.threshold
+        )));
+    }
+    let len = points[0].1.len();
+    if points.iter().any(|(_, data)| data.len() != len) {
+        return Err(ZkpError::InvalidInput("Key shares have different lengths".to_string()));
+    }
+
+    // Lagrange interpolation at x = 0; in GF(256) subtraction is xor
+    let mut secret = vec![0u8; len];
+    for (j, (xj, yj)) in points.iter().enumerate() {
+        let mut basis = 1u8;
+        for (k, (xk, _)) in points.iter().enumerate() {
+            if k != j {
+                basis = mul(basis, mul(*xk, inverse(xk ^ xj)));
+            }
+        }
+        for (byte, y) in secret.iter_mut().zip(yj) {
+            *byte ^= mul(*y, basis);
+        }
+    }
+    Ok(secret)
+}
+
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    // Horner's rule
+    coefficients.iter().rev().fold(0u8, |acc, c| mul(acc, x) ^ c)
+}
+
+// Multiplication modulo the AES polynomial x^8 + x^4 + x^3 + x + 1
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 = a^-1 for a != 0
+fn inverse(a: u8) -> u8 {
+    let mut result = 1u8;
+    for _ in 0..254 {
+        result = mul(result, a);
+    }
+    result
+}
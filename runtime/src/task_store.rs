@@ -0,0 +1,242 @@
+//! Storage for proof tasks, so queued work and finished results survive restarts.
+//!
+//! Changes to an existing task go through `update`, which reads, modifies and writes the task
+//! atomically with respect to every other write, so concurrent status changes, webhook records
+//! and sweeps cannot lose each other's updates.
+
+use crate::errors::{ZkpError, ZkpResult};
+use crate::keystore;
+use crate::types::ProofTask;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Changes a stored task in place; returning false leaves the stored task untouched
+pub type TaskUpdate<'a> = Box<dyn FnOnce(&mut ProofTask) -> bool + 'a>;
+
+pub trait TaskStore: Send + Sync {
+    fn put(&self, task_id: &str, task: &ProofTask) -> ZkpResult<()>;
+    fn get(&self, task_id: &str) -> ZkpResult<Option<ProofTask>>;
+    fn list(&self) -> ZkpResult<Vec<(String, ProofTask)>>;
+    fn remove(&self, task_id: &str) -> ZkpResult<()>;
+    /// Apply `update` to a stored task and write it back. Returns the updated task, or `None` if
+    /// the task does not exist or `update` declined the change.
+    fn update(&self, task_id: &str, update: TaskUpdate<'_>) -> ZkpResult<Option<ProofTask>>;
+}
+
+/// Run store operations on the blocking pool; the file store writes and fsyncs synchronously
+pub async fn run_blocking<T, F>(tasks: &Arc<dyn TaskStore>, work: F) -> ZkpResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn TaskStore) -> ZkpResult<T> + Send + 'static,
+{
+    let tasks = tasks.clone();
+    tokio::task::spawn_blocking(move || work(tasks.as_ref()))
+        .await
+        .map_err(|e| ZkpError::StateError(format!("Task store operation panicked: {}", e)))?
+}
+
+/// Volatile store; everything is lost on restart
+#[derive(Default)]
+pub struct MemoryTaskStore {
+    tasks: Mutex<HashMap<String, ProofTask>>,
+}
+
+impl TaskStore for MemoryTaskStore {
+    fn put(&self, task_id: &str, task: &ProofTask) -> ZkpResult<()> {
+        self.tasks.lock().unwrap().insert(task_id.to_string(), task.clone());
+        Ok(())
+    }
+
+    fn get(&self, task_id: &str) -> ZkpResult<Option<ProofTask>> {
+        Ok(self.tasks.lock().unwrap().get(task_id).cloned())
+    }
+
+    fn list(&self) -> ZkpResult<Vec<(String, ProofTask)>> {
+        let tasks = self.tasks.lock().unwrap();
+        Ok(tasks.iter().map(|(id, task)| (id.clone(), task.clone())).collect())
+    }
+
+    fn remove(&self, task_id: &str) -> ZkpResult<()> {
+        self.tasks.lock().unwrap().remove(task_id);
+        Ok(())
+    }
+
+    fn update(&self, task_id: &str, update: TaskUpdate<'_>) -> ZkpResult<Option<ProofTask>> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let Some(task) = tasks.get_mut(task_id) else {
+            return Ok(None);
+        };
+        let mut updated = task.clone();
+        if !update(&mut updated) {
+            return Ok(None);
+        }
+        *task = updated.clone();
+        Ok(Some(updated))
+    }
+}
+
+/// One JSON file per task in a directory, written atomically
+pub struct FileTaskStore {
+    dir: PathBuf,
+    // Held for every write, so an update's read-modify-write is not interleaved with another
+    writes: Mutex<()>,
+}
+
+impl FileTaskStore {
+    pub fn open(dir: PathBuf) -> ZkpResult<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            writes: Mutex::new(()),
+        })
+    }
+
+    fn path(&self, task_id: &str) -> ZkpResult<PathBuf> {
+        // Task ids come from URLs; only allow names that cannot leave the store directory
+        if task_id.is_empty() || !task_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(ZkpError::InvalidInput(format!("Invalid task id: {}", task_id)));
+        }
+        Ok(self.dir.join(format!("{}.json", task_id)))
+    }
+}
+
+impl TaskStore for FileTaskStore {
+    fn put(&self, task_id: &str, task: &ProofTask) -> ZkpResult<()> {
+        let _writes = self.writes.lock().unwrap();
+        keystore::write_private_file(&self.path(task_id)?, &serde_json::to_vec(task)?)
+    }
+
+    fn get(&self, task_id: &str) -> ZkpResult<Option<ProofTask>> {
+        match std::fs::read(self.path(task_id)?) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn list(&self) -> ZkpResult<Vec<(String, ProofTask)>> {
+        let mut tasks = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(task_id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            match std::fs::read(&path).map_err(ZkpError::from).and_then(|c| Ok(serde_json::from_slice(&c)?)) {
+                Ok(task) => tasks.push((task_id.to_string(), task)),
+                Err(e) => eprintln!("Skipping unreadable task file {}: {}", path.display(), e),
+            }
+        }
+        Ok(tasks)
+    }
+
+    fn remove(&self, task_id: &str) -> ZkpResult<()> {
+        let _writes = self.writes.lock().unwrap();
+        match std::fs::remove_file(self.path(task_id)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&self, task_id: &str, update: TaskUpdate<'_>) -> ZkpResult<Option<ProofTask>> {
+        let _writes = self.writes.lock().unwrap();
+        let Some(mut task) = self.get(task_id)? else {
+            return Ok(None);
+        };
+        if !update(&mut task) {
+            return Ok(None);
+        }
+        keystore::write_private_file(&self.path(task_id)?, &serde_json::to_vec(&task)?)?;
+        Ok(Some(task))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keystore::tests::TempDir;
+    use crate::types::{ProofStatus, WebhookAttempt};
+
+    fn pending_task() -> ProofTask {
+        ProofTask {
+            status: ProofStatus::Pending,
+            submitted_at: 0,
+            queued: None,
+            finished_at: None,
+            callback_url: None,
+            webhook_attempts: Vec::new(),
+            batch: None,
+        }
+    }
+
+    fn attempt(attempt: u32) -> WebhookAttempt {
+        WebhookAttempt {
+            attempt,
+            attempted_at: 0,
+            status_code: None,
+            error: None,
+            delivered: false,
+        }
+    }
+
+    // Many writers appending at once must not lose each other's changes
+    fn concurrent_updates_are_not_lost(store: Arc<dyn TaskStore>) {
+        store.put("task", &pending_task()).unwrap();
+        let writers: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for n in 0..25 {
+                        let updated = store
+                            .update(
+                                "task",
+                                Box::new(move |task| {
+                                    task.webhook_attempts.push(attempt(n));
+                                    true
+                                }),
+                            )
+                            .unwrap();
+                        assert!(updated.is_some());
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(store.get("task").unwrap().unwrap().webhook_attempts.len(), 200);
+    }
+
+    fn declined_and_missing_updates(store: Arc<dyn TaskStore>) {
+        assert!(store.update("missing", Box::new(|_| true)).unwrap().is_none());
+
+        store.put("task", &pending_task()).unwrap();
+        let declined = store
+            .update(
+                "task",
+                Box::new(|task| {
+                    task.status = ProofStatus::InProgress;
+                    false
+                }),
+            )
+            .unwrap();
+        assert!(declined.is_none());
+        assert!(matches!(store.get("task").unwrap().unwrap().status, ProofStatus::Pending));
+    }
+
+    #[test]
+    fn memory_store_updates() {
+        concurrent_updates_are_not_lost(Arc::new(MemoryTaskStore::default()));
+        declined_and_missing_updates(Arc::new(MemoryTaskStore::default()));
+    }
+
+    #[test]
+    fn file_store_updates() {
+        let dir = TempDir::new();
+        concurrent_updates_are_not_lost(Arc::new(FileTaskStore::open(dir.0.join("concurrent")).unwrap()));
+        declined_and_missing_updates(Arc::new(FileTaskStore::open(dir.0.join("declined")).unwrap()));
+    }
+}
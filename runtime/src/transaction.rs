@@ -0,0 +1,213 @@
+//! EIP-1559 (type 2) transaction encoding and signing.
+
+use crate::errors::{ZkpError, ZkpResult};
+use crate::ethereum::{self, decode_hex_bytes, parse_address, parse_uint256};
+use secp256k1::{Secp256k1, SecretKey as SecpSecretKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const TRANSACTION_TYPE: u8 = 0x02;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListItem {
+    pub address: String,
+    #[serde(default)]
+    pub storage_keys: Vec<String>,
+}
+
+/// Unsigned transaction fields. Quantities may be JSON numbers, decimal strings or 0x-hex strings.
+/// `to` is omitted for contract creation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Eip1559Transaction {
+    pub chain_id: Value,
+    pub nonce: Value,
+    pub max_priority_fee_per_gas: Value,
+    pub max_fee_per_gas: Value,
+    #[serde(alias = "gasLimit")]
+    pub gas: Value,
+    #[serde(default)]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub value: Option<Value>,
+    #[serde(default, alias = "input")]
+    pub data: Option<String>,
+    #[serde(default)]
+    pub access_list: Vec<AccessListItem>,
+}
+
+pub struct SignedTransaction {
+    pub raw_transaction: Vec<u8>,
+    pub transaction_hash: [u8; 32],
+    pub signing_hash: [u8; 32],
+}
+
+enum Rlp {
+    Bytes(Vec<u8>),
+    List(Vec<Rlp>),
+}
+
+impl Rlp {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Rlp::Bytes(bytes) if bytes.len() == 1 && bytes[0] < 0x80 => out.push(bytes[0]),
+            Rlp::Bytes(bytes) => {
+                encode_length(bytes.len(), 0x80, out);
+                out.extend_from_slice(bytes);
+            }
+            Rlp::List(items) => encode_list(items, out),
+        }
+    }
+}
+
+fn encode_list(items: &[Rlp], out: &mut Vec<u8>) {
+    let mut payload = Vec::new();
+    for item in items {
+        item.encode(&mut payload);
+    }
+    encode_length(payload.len(), 0xc0, out);
+    out.extend_from_slice(&payload);
+}
+
+fn encode_length(len: usize, offset: u8, out: &mut Vec<u8>) {
+    if len <= 55 {
+        out.push(offset + len as u8);
+    } else {
+        let len_bytes = trim_leading_zeros(&len.to_be_bytes());
+        out.push(offset + 55 + len_bytes.len() as u8);
+        out.extend_from_slice(&len_bytes);
+    }
+}
+
+fn trim_leading_zeros(bytes: &[u8]) -> Vec<u8> {
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    bytes[start..].to_vec()
+}
+
+fn quantity(value: &Value, field: &str) -> ZkpResult<Rlp> {
+    let word = parse_uint256(value)
+        .map_err(|e| ZkpError::InvalidInput(format!("Invalid transaction field {}: {}", field, e)))?;
+    Ok(Rlp::Bytes(trim_leading_zeros(&word)))
+}
+
+impl Eip1559Transaction {
+    fn fields(&self) -> ZkpResult<Vec<Rlp>> {
+        if parse_uint256(&self.max_priority_fee_per_gas)? > parse_uint256(&self.max_fee_per_gas)? {
+            return Err(ZkpError::InvalidInput(
+                "maxPriorityFeePerGas must not exceed maxFeePerGas".to_string(),
+            ));
+        }
+
+        let to = match &self.to {
+            Some(to) => parse_address(to)?.to_vec(),
+            None => Vec::new(),
+        };
+        let data = match &self.data {
+            Some(data) => decode_hex_bytes(data)?,
+            None => Vec::new(),
+        };
+        let access_list = self
+            .access_list
+            .iter()
+            .map(|item| {
+                let storage_keys = item
+                    .storage_keys
+                    .iter()
+                    .map(|key| {
+                        let key = decode_hex_bytes(key)?;
+                        if key.len() != 32 {
+                            return Err(ZkpError::InvalidInput("Storage keys must be 32 bytes".to_string()));
+                        }
+                        Ok(Rlp::Bytes(key))
+                    })
+                    .collect::<ZkpResult<Vec<_>>>()?;
+                Ok(Rlp::List(vec![
+                    Rlp::Bytes(parse_address(&item.address)?.to_vec()),
+                    Rlp::List(storage_keys),
+                ]))
+            })
+            .collect::<ZkpResult<Vec<_>>>()?;
+
+        Ok(vec![
+            quantity(&self.chain_id, "chainId")?,
+            quantity(&self.nonce, "nonce")?,
+            quantity(&self.max_priority_fee_per_gas, "maxPriorityFeePerGas")?,
+            quantity(&self.max_fee_per_gas, "maxFeePerGas")?,
+            quantity(&self.gas, "gas")?,
+            Rlp::Bytes(to),
+            quantity(self.value.as_ref().unwrap_or(&Value::from(0)), "value")?,
+            Rlp::Bytes(data),
+            Rlp::List(access_list),
+        ])
+    }
+
+    /// Sign as 0x02 || rlp([chainId, nonce, maxPriorityFeePerGas, maxFeePerGas, gas, to, value,
+    /// data, accessList, yParity, r, s])
+    pub fn sign(&self, secp: &Secp256k1<secp256k1::All>, secret_key: &SecpSecretKey) -> ZkpResult<SignedTransaction> {
+        let mut fields = self.fields()?;
+
+        let mut unsigned = vec![TRANSACTION_TYPE];
+        encode_list(&fields, &mut unsigned);
+        let signing_hash = ethereum::keccak256(&unsigned);
+
+        let signature = ethereum::sign_digest(secp, secret_key, &signing_hash)?;
+        fields.push(Rlp::Bytes(trim_leading_zeros(&[signature[64] - 27])));
+        fields.push(Rlp::Bytes(trim_leading_zeros(&signature[..32])));
+        fields.push(Rlp::Bytes(trim_leading_zeros(&signature[32..64])));
+
+        let mut raw_transaction = vec![TRANSACTION_TYPE];
+        encode_list(&fields, &mut raw_transaction);
+
+        Ok(SignedTransaction {
+            transaction_hash: ethereum::keccak256(&raw_transaction),
+            raw_transaction,
+            signing_hash,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference values computed independently (Python RLP, keccak256 and RFC 6979 signing)
+    #[test]
+    fn signs_reference_transaction() {
+        let transaction: Eip1559Transaction = serde_json::from_value(serde_json::json!({
+            "chainId": 1,
+            "nonce": 9,
+            "maxPriorityFeePerGas": "2000000000",
+            "maxFeePerGas": "0x174876e800",
+            "gas": 25300,
+            "to": "0x3535353535353535353535353535353535353535",
+            "value": "1000000000000000000",
+            "data": "0xabcdef",
+            "accessList": [{
+                "address": "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae",
+                "storageKeys": ["0x0000000000000000000000000000000000000000000000000000000000000003"],
+            }],
+        }))
+        .unwrap();
+        let secret_key = SecpSecretKey::from_slice(&[0x46; 32]).unwrap();
+        let signed = transaction.sign(&Secp256k1::new(), &secret_key).unwrap();
+
+        assert_eq!(
+            hex::encode(signed.signing_hash),
+            "b03591e55d5d190d3637ca5fcdba751b47b48e950319bbff11b160169b05d75a"
+        );
+        assert_eq!(
+            hex::encode(&signed.raw_transaction),
+            concat!(
+                "02f8af0109847735940085174876e8008262d4943535353535353535353535353535353535353535880de0b6b3",
+                "a764000083abcdeff838f794de0b295669a9fd93d5f28d9ec85e40f4cb697baee1a00000000000000000000000",
+                "00000000000000000000000000000000000000000380a0ab8d5fa1926e7ebef99f35be803c9c1abd927b649e87",
+                "62665dda6189fe07e5b9a025e02eeadb45314ede857380e632f6b97bf9c55764cc67429cdc655c25ef6ae9",
+            )
+        );
+        assert_eq!(
+            hex::encode(signed.transaction_hash),
+            "1d7be11c21a5ed662f9a5b746cfabe8c987a0034d60f5cca82243be918631263"
+        );
+    }
+}
@@ -1,63 +1,398 @@
 use serde::{Deserialize, Serialize};
-use secp256k1::{Secp256k1, SecretKey as SecpSecretKey};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
+use secp256k1::Secp256k1;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use crate::eip712::TypedData;
+use crate::encryption::EncryptionSuite;
+use crate::hd::ExtendedKey;
+use crate::transaction::Eip1559Transaction;
+use crate::keyring::{KeyRing, KeyTransition, KeyVersionInfo};
+use crate::queue::TaskQueue;
+use crate::session::SessionManager;
+use crate::task_store::TaskStore;
 
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
 pub enum ProofStatus {
     Pending,
     InProgress,
-    Completed { proof: String },
-    Failed { error: String },
+    Completed { proof: String, receipt: ProofReceipt },
+    /// Completed, with the proof and receipt sealed to the requester's key (hex ECIES payload)
+    CompletedSealed { sealed_output: String },
+    Failed {
+        error: String,
+        #[serde(default)]
+        code: Option<String>,
+    },
+    Cancelled,
+    /// Finished task removed by the retention sweeper
+    Expired { expired_at: i64 },
+}
+
+impl ProofStatus {
+    /// Status name reported by the API
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProofStatus::Pending => "pending",
+            ProofStatus::InProgress => "in_progress",
+            ProofStatus::Completed { .. } | ProofStatus::CompletedSealed { .. } => "completed",
+            ProofStatus::Failed { .. } => "failed",
+            ProofStatus::Cancelled => "cancelled",
+            ProofStatus::Expired { .. } => "expired",
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, ProofStatus::Pending | ProofStatus::InProgress)
+    }
+}
+
+/// Scheduling class of a proof task. Interactive requests should use `high`; bulk work `low`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofPriority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl ProofPriority {
+    pub const ALL: [ProofPriority; 3] = [ProofPriority::High, ProofPriority::Normal, ProofPriority::Low];
+
+    /// 0 for the most urgent class
+    pub fn rank(self) -> usize {
+        self as usize
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Service-signed statement that this prover produced `proof_hash` for the given circuit and input.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofReceipt {
+    pub task_id: String,
+    pub circuit_hash: String,
+    pub input_commitment: String,
+    pub proof_hash: String,
+    pub completed_at: i64,
+    pub digest: String,
+    pub signature: String,
+    pub signer: String,
+    pub key_version: u32,
+}
+
+/// A task as kept in the task store. `queued` holds what is needed to (re-)run the task and is
+/// dropped once the task finishes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProofTask {
     pub status: ProofStatus,
+    pub submitted_at: i64,
+    pub queued: Option<QueuedProofTask>,
+    #[serde(default)]
+    pub finished_at: Option<i64>,
+    /// Where the result is POSTed once the task completes or fails
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    #[serde(default)]
+    pub webhook_attempts: Vec<WebhookAttempt>,
+    #[serde(default)]
+    pub batch: Option<BatchRef>,
+}
+
+/// Membership of a task in a batch submission
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRef {
+    pub batch_id: String,
+    /// Position of the task's request in the batch
+    pub index: usize,
+}
+
+/// One delivery of a finished task to its callback URL
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookAttempt {
+    pub attempt: u32,
+    pub attempted_at: i64,
+    /// HTTP status of the callback's response, if one was received
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueuedProofTask {
     pub task_id: String,
     pub circuit_path: String,
-    pub input: serde_json::Value,
+    pub input: TaskInput,
     pub mock_mode: bool,
+    pub recipient: Option<secp256k1::PublicKey>,
+    /// Proving deadline, already capped by the server maximum
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub priority: ProofPriority,
+}
+
+/// Proof input as held in the queue and task store. It stays sealed to the service key until a
+/// worker proves it; plaintext inputs are sealed on submission.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskInput {
+    pub suite: EncryptionSuite,
+    /// Version of the service key the payload is sealed to; absent on tasks stored before
+    /// versions were recorded, which are opened with the active key
+    #[serde(default)]
+    pub key_version: Option<u32>,
+    #[serde(with = "hex_bytes")]
+    pub payload: Vec<u8>,
+    /// Salt of the input commitment, sealed with secp256k1 ECIES to the same key version under
+    /// `encryption::INPUT_SALT_CONTEXT`; empty on tasks stored before commitments were salted
+    #[serde(default, with = "hex_bytes")]
+    pub sealed_salt: Vec<u8>,
+}
+
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        hex::decode(value.trim_start_matches("0x")).map_err(serde::de::Error::custom)
+    }
 }
 
+/// Proof input sealed to the service: a hex payload in the named encryption suite (default
+/// secp256k1 ECIES) whose plaintext is the circuit input JSON, bound to
+/// `encryption::PROOF_INPUT_CONTEXT` (see the `encryption` module for how each suite binds it)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EncryptedInput {
+    pub ciphertext: String,
+    #[serde(default)]
+    pub suite: Option<String>,
+    /// Service key version the payload is sealed to (see `/public-key`); defaults to the active one
+    #[serde(default)]
+    pub key_version: Option<u32>,
+}
+
+/// Exactly one of `input` (plaintext) and `encrypted_input` must be given
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[allow(dead_code)]
 pub struct ProofRequest {
     pub circuit_path: String,
-    pub input: serde_json::Value,
+    #[serde(default)]
+    pub input: Option<serde_json::Value>,
+    #[serde(default)]
+    pub encrypted_input: Option<EncryptedInput>,
+    pub mock: bool,
+    /// When set, the result is only returned sealed to this secp256k1 public key
+    #[serde(default)]
+    pub recipient_public_key: Option<String>,
+    /// Proving timeout in seconds, capped by the server maximum
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub priority: ProofPriority,
+    /// http(s) URL that receives the signed result when the task completes or fails
+    #[serde(default)]
+    pub callback_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchProofRequest {
+    pub requests: Vec<ProofRequest>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchSubmitResponse {
+    pub batch_id: String,
+    /// Task ids in the order of the submitted requests
+    pub task_ids: Vec<String>,
+    /// Input commitment salt of each task, in the same order (see `ProofResponse::input_salt`)
+    pub input_salts: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchStatusResponse {
+    pub batch_id: String,
+    pub total: usize,
+    /// Number of tasks per status. Tasks whose expired marker has also been dropped count as
+    /// `missing`.
+    pub counts: BTreeMap<String, usize>,
+    pub finished: bool,
+    /// Every task's result in submission order, once all have finished
+    pub results: Option<Vec<ProofResponse>>,
+}
+
+/// Exactly one of `circuit_path` (compiled circuit to derive the key from) and
+/// `verification_key` (hex) must be given. `proof` is hex; public inputs are field elements as
+/// 0x-hex or decimal strings.
+#[derive(Debug, Deserialize)]
+pub struct VerifyProofRequest {
+    pub proof: String,
+    #[serde(default)]
+    pub public_inputs: Vec<String>,
+    #[serde(default)]
+    pub circuit_path: Option<String>,
+    #[serde(default)]
+    pub verification_key: Option<String>,
+    /// Only accepted by a mock-mode service, which checks every proof as a mock proof
+    #[serde(default)]
     pub mock: bool,
 }
 
+#[derive(Debug, Serialize)]
+pub struct VerifyProofResponse {
+    pub valid: bool,
+    /// Why the proof was rejected
+    pub reason: Option<String>,
+    /// "bb", or "mock" for mock proofs
+    pub verifier: String,
+}
+
+/// Plaintext of `sealed_output`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedProofOutput {
+    pub task_id: String,
+    pub proof: String,
+    pub receipt: ProofReceipt,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[allow(dead_code)]
 pub struct ProofResponse {
     pub task_id: String,
     pub status: String,
     pub proof: Option<String>,
+    pub receipt: Option<ProofReceipt>,
+    pub sealed_output: Option<String>,
     pub error: Option<String>,
+    pub error_code: Option<String>,
+    /// 1-based position in the proof queue while pending
+    pub queue_position: Option<usize>,
+    /// Expected time until proving starts, from recent proving times
+    pub estimated_wait_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub webhook_attempts: Vec<WebhookAttempt>,
+    /// Salt of the receipt's input commitment, keccak256(salt ‖ input). Only returned when the
+    /// task is submitted, so the submitter alone can open the commitment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_salt: Option<String>,
+}
+
+/// Step of the proving pipeline a running task is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofPhase {
+    /// Opening the input and solving the circuit for it (`nargo execute`)
+    WitnessGeneration,
+    /// Proving from the witness (`bb prove`)
+    Proving,
+    /// Checking the new proof against the circuit's verification key (`bb verify`)
+    Verifying,
+}
+
+/// A status change or proving phase of one task, as pushed to streaming clients. Finished tasks
+/// carry their proof or error like `/retrieve-output` does.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskEvent {
+    #[serde(flatten)]
+    pub task: ProofResponse,
+    pub phase: Option<ProofPhase>,
+    pub timestamp: i64,
+    #[serde(skip)]
+    pub finished: bool,
+}
+
+/// Tasks to stream events for, as a comma-separated list of task ids
+#[derive(Debug, Deserialize)]
+pub struct TaskEventsQuery {
+    pub task_ids: String,
+}
+
+impl TaskEventsQuery {
+    pub fn task_ids(&self) -> Vec<String> {
+        self.task_ids
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
 }
 
 #[allow(dead_code)]
 pub struct ZkpService {
     pub secp: Arc<Secp256k1<secp256k1::All>>,
-    pub secret_key: Arc<SecpSecretKey>,
+    pub keyring: Arc<RwLock<KeyRing>>,
+    /// Held for a whole rotation, which writes the keystore without locking the key ring
+    pub key_rotation: Arc<Mutex<()>>,
+    pub hd_master: Arc<ExtendedKey>,
     pub state: Arc<Mutex<HashMap<String, String>>>,
-    pub active_proofs: Arc<Mutex<HashMap<String, ProofTask>>>,
-    pub task_sender: mpsc::UnboundedSender<QueuedProofTask>,
+    pub tasks: Arc<dyn TaskStore>,
+    pub queue: Arc<TaskQueue>,
     pub mock_mode: bool,
     pub tracked_directories: Arc<Mutex<HashMap<String, String>>>,
+    pub runtime_info: Arc<tokio::sync::OnceCell<RuntimeInfo>>,
+    pub proof_timeout_max_secs: u64,
+    /// One permit per verifier allowed to run at once
+    pub verify_permits: Arc<tokio::sync::Semaphore>,
+    pub sessions: Arc<SessionManager>,
+    pub events: tokio::sync::broadcast::Sender<TaskEvent>,
+    /// Task ids of each batch in submission order, rebuilt from the task store at startup
+    pub batches: Arc<Mutex<HashMap<String, Vec<String>>>>,
+}
+
+/// Build and environment facts covered by attestations, probed once on first use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeInfo {
+    pub binary_hash: String,
+    pub prover_backends: Vec<String>,
+    pub toolchain: BTreeMap<String, Option<String>>,
 }
 
 // API Request/Response types
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PublicKeyResponse {
     pub public_key: String,
+    pub compressed_public_key: String,
+    pub address: String,
+    pub fingerprint: String,
+    pub key_version: u32,
+    pub keys: Vec<PublicKeyEntry>,
+    pub transitions: Vec<KeyTransition>,
+    pub encryption_keys: Vec<EncryptionKeyInfo>,
+}
+
+/// Public key to encrypt to for one encryption suite
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncryptionKeyInfo {
+    pub suite: String,
+    pub public_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicKeyEntry {
+    #[serde(flatten)]
+    pub info: KeyVersionInfo,
+    pub compressed_public_key: String,
+    pub address: String,
+    pub fingerprint: String,
+}
+
+/// Selects a tenant key instead of the service key
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PublicKeyQuery {
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DerivedPublicKeyResponse {
+    pub public_key: String,
+    pub compressed_public_key: String,
+    pub address: String,
+    pub fingerprint: String,
+    pub tenant_id: String,
+    pub derivation_path: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -93,12 +428,23 @@ pub struct SubmitXResponse {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DecryptInputRequest {
+    /// Hex-encoded payload addressed to the service key in `suite`
     pub encrypted_data: String,
+    /// Encryption suite identifier; defaults to secp256k1 ECIES
+    #[serde(default)]
+    pub suite: Option<String>,
+    /// Service key version the payload is sealed to; defaults to the active one
+    #[serde(default)]
+    pub key_version: Option<u32>,
+    /// "utf8" (default) or "hex"
+    #[serde(default)]
+    pub encoding: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DecryptInputResponse {
     pub decrypted: String,
+    pub encoding: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -124,12 +470,116 @@ pub struct TrackedDirectoriesResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SignMessageRequest {
     pub message: String,
+    /// Sign with this tenant's key; only the operator (admin token) may sign for tenants
+    #[serde(default)]
+    pub tenant_id: Option<String>,
 }
 
+/// `key_version` is set for the service key, `derivation_path` for tenant keys.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SignMessageResponse {
     pub signature: String,
     pub public_key: String,
+    pub key_version: Option<u32>,
+    pub derivation_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignTypedDataRequest {
+    pub typed_data: TypedData,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignTypedDataResponse {
+    pub signature: String,
+    pub digest: String,
+    pub public_key: String,
+    pub key_version: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignTransactionRequest {
+    pub transaction: Eip1559Transaction,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignTransactionResponse {
+    pub raw_transaction: String,
+    pub transaction_hash: String,
+    pub signing_hash: String,
+    pub from: String,
+    pub key_version: u32,
+}
+
+/// Either `message` (hashed with EIP-191) or a raw 32-byte `digest` must be given.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifySignatureRequest {
+    pub message: Option<String>,
+    pub digest: Option<String>,
+    pub signature: String,
+    pub expected_signer: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifySignatureResponse {
+    pub recovered_address: String,
+    pub recovered_public_key: String,
+    pub digest: String,
+    pub matches_expected: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttestationRequest {
+    pub nonce: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationDocument {
+    pub nonce: String,
+    pub public_key: String,
+    pub address: String,
+    pub key_version: u32,
+    pub crate_name: String,
+    pub crate_version: String,
+    pub binary_hash: String,
+    pub mock_mode: bool,
+    pub prover_backends: Vec<String>,
+    pub toolchain: BTreeMap<String, Option<String>>,
+    pub issued_at: String,
+}
+
+/// `payload` is the exact JSON serialization of `document` that was signed with EIP-191
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttestationResponse {
+    pub document: AttestationDocument,
+    pub payload: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionHandshakeRequest {
+    /// Client ephemeral secp256k1 public key, compressed or uncompressed hex
+    pub client_public_key: String,
+}
+
+/// `signature` is EIP-191 over `digest` = keccak256("zkpruntime session v1" ‖ session_id ‖
+/// client key ‖ server key (both compressed) ‖ uint256 expires_at), made with the service key
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionHandshakeResponse {
+    pub session_id: String,
+    pub server_public_key: String,
+    pub expires_at: i64,
+    pub digest: String,
+    pub signature: String,
+    pub signer: String,
+    pub key_version: u32,
+}
+
+/// Body of requests and responses on a session channel
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionEnvelope {
+    pub seq: u64,
+    pub ciphertext: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
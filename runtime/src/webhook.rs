@@ -0,0 +1,323 @@
+//! Signed delivery of finished task results to the callback URL given at submission.
+//!
+//! Each delivery POSTs the task's `ProofResponse` JSON with these headers:
+//!   x-zkp-timestamp: unix seconds at signing time
+//!   x-zkp-signature: EIP-191 signature (r‖s‖v) over keccak256("<timestamp>." ‖ body)
+//!   x-zkp-signer:    address of the service key that signed
+//! Receivers should recover the signer, compare it with the service address and reject stale
+//! timestamps. Failed deliveries are retried with exponential backoff, and every attempt is
+//! recorded on the stored task.
+//!
+//! Callbacks only go to public addresses: loopback, private, link-local, multicast and unspecified
+//! hosts are rejected at submission, and host names are checked again when they are resolved for
+//! each delivery, so a name cannot be pointed at an internal address later. Redirects are not
+//! followed.
+
+use crate::errors::{ZkpError, ZkpResult};
+use crate::ethereum;
+use crate::keyring::KeyRing;
+use crate::task_store::{self, TaskStore};
+use crate::types::{ProofStatus, WebhookAttempt};
+use secp256k1::Secp256k1;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// Delay before the second attempt; doubled for each attempt after that
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+pub struct WebhookSender {
+    client: reqwest::Client,
+    secp: Arc<Secp256k1<secp256k1::All>>,
+    keyring: Arc<RwLock<KeyRing>>,
+    tasks: Arc<dyn TaskStore>,
+    max_attempts: u32,
+}
+
+impl WebhookSender {
+    pub fn new(
+        secp: Arc<Secp256k1<secp256k1::All>>,
+        keyring: Arc<RwLock<KeyRing>>,
+        tasks: Arc<dyn TaskStore>,
+        max_attempts: u32,
+        timeout: Duration,
+    ) -> ZkpResult<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            // A proxy would resolve the host itself, past the address check
+            .no_proxy()
+            .dns_resolver(Arc::new(PublicResolver))
+            .build()
+            .map_err(|e| ZkpError::StateError(format!("Failed to build webhook client: {}", e)))?;
+        Ok(Self {
+            client,
+            secp,
+            keyring,
+            tasks,
+            max_attempts: max_attempts.max(1),
+        })
+    }
+
+    /// Deliver `body` for a task in the background, continuing after `previous_attempts`
+    pub fn spawn(self: &Arc<Self>, task_id: String, url: String, body: Vec<u8>, previous_attempts: u32) {
+        let sender = self.clone();
+        tokio::spawn(async move { sender.deliver(&task_id, &url, &body, previous_attempts).await });
+    }
+
+    /// Whether a task still has webhook attempts to make, e.g. after a restart cut them short
+    pub fn is_pending(&self, attempts: &[WebhookAttempt]) -> bool {
+        !attempts.iter().any(|attempt| attempt.delivered) && (attempts.len() as u32) < self.max_attempts
+    }
+
+    async fn deliver(&self, task_id: &str, url: &str, body: &[u8], previous_attempts: u32) {
+        for attempt in previous_attempts + 1..=self.max_attempts {
+            if attempt > 1 {
+                tokio::time::sleep(backoff(attempt)).await;
+            }
+
+            let attempted_at = chrono::Utc::now().timestamp();
+            let (status_code, error) = match self.post(url, body, attempted_at).await {
+                Ok(status) if status.is_success() => (Some(status.as_u16()), None),
+                Ok(status) => (Some(status.as_u16()), Some(format!("Callback responded with {}", status))),
+                Err(e) => (None, Some(e)),
+            };
+            let delivered = error.is_none();
+            let record = WebhookAttempt {
+                attempt,
+                attempted_at,
+                status_code,
+                error,
+                delivered,
+            };
+            match self.record(task_id, record).await {
+                Ok(true) => {}
+                // The task is gone or expired; nothing left to report
+                Ok(false) => return,
+                Err(e) => eprintln!("Failed to record webhook attempt for task {}: {}", task_id, e),
+            }
+            if delivered {
+                return;
+            }
+        }
+        eprintln!("Giving up on webhook for task {} after {} attempts", task_id, self.max_attempts);
+    }
+
+    async fn post(&self, url: &str, body: &[u8], timestamp: i64) -> Result<reqwest::StatusCode, String> {
+        // Tasks stored before a URL rule changed are held to the current rules
+        parse_callback_url(url).map_err(|e| e.to_string())?;
+
+        let mut signed = format!("{}.", timestamp).into_bytes();
+        signed.extend_from_slice(body);
+        let digest = ethereum::keccak256(&signed);
+
+        let (signature, signer) = {
+            let keyring = self.keyring.read().unwrap();
+            let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, keyring.active_key());
+            (
+                ethereum::sign_personal_message(&self.secp, keyring.active_key(), &digest)
+                    .map_err(|e| e.to_string())?,
+                ethereum::format_address(&ethereum::address_from_public_key(&public_key)),
+            )
+        };
+
+        let response = self
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("x-zkp-timestamp", timestamp.to_string())
+            .header("x-zkp-signature", signature)
+            .header("x-zkp-signer", signer)
+            .body(body.to_vec())
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+        Ok(response.status())
+    }
+
+    // Returns false once the task no longer exists or has expired
+    async fn record(&self, task_id: &str, attempt: WebhookAttempt) -> ZkpResult<bool> {
+        let id = task_id.to_string();
+        let updated = task_store::run_blocking(&self.tasks, move |tasks| {
+            tasks.update(
+                &id,
+                Box::new(|task| {
+                    if matches!(task.status, ProofStatus::Expired { .. }) {
+                        return false;
+                    }
+                    task.webhook_attempts.push(attempt);
+                    true
+                }),
+            )
+        })
+        .await?;
+        Ok(updated.is_some())
+    }
+}
+
+/// Validate a callback URL given at submission. Host names are checked when they are resolved.
+pub fn parse_callback_url(url: &str) -> ZkpResult<String> {
+    let parsed =
+        reqwest::Url::parse(url).map_err(|e| ZkpError::InvalidInput(format!("Invalid callback URL: {}", e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(ZkpError::InvalidInput("Callback URL must use http or https".to_string()));
+    }
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| ZkpError::InvalidInput("Callback URL has no host".to_string()))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+    let internal = match host.parse::<IpAddr>() {
+        Ok(ip) => !is_public(ip),
+        Err(_) => host == "localhost" || host.ends_with(".localhost"),
+    };
+    if internal {
+        return Err(ZkpError::InvalidInput(format!(
+            "Callback URL must point to a public host, not {}",
+            host
+        )));
+    }
+    Ok(parsed.to_string())
+}
+
+// Whether callbacks may be sent to `ip`
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_multicast()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // 0.0.0.0/8 reaches this host on some systems; 100.64.0.0/10 is carrier-grade NAT
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let embedded = |high: u16, low: u16| Ipv4Addr::from(((high as u32) << 16) | low as u32);
+            match segments {
+                // Addresses that reach an IPv4 host are judged by it: IPv4-mapped (::ffff:0:0/96),
+                // IPv4-compatible (::/96, which also covers :: and ::1), NAT64 (64:ff9b::/96) and
+                // 6to4 (2002::/16)
+                [0, 0, 0, 0, 0, 0xffff, high, low]
+                | [0, 0, 0, 0, 0, 0, high, low]
+                | [0x64, 0xff9b, 0, 0, 0, 0, high, low]
+                | [0x2002, high, low, ..] => is_public(IpAddr::V4(embedded(high, low))),
+                // Local-use NAT64 (64:ff9b:1::/48) translates to addresses of the operator's choosing
+                [0x64, 0xff9b, 1, ..] => false,
+                _ => {
+                    !(ip.is_loopback()
+                        || ip.is_unspecified()
+                        || ip.is_multicast()
+                        || ip.is_unique_local()
+                        || ip.is_unicast_link_local()
+                        // Deprecated site-local, fec0::/10
+                        || segments[0] & 0xffc0 == 0xfec0)
+                }
+            }
+        }
+    }
+}
+
+// Resolves callback hosts to their public addresses only, so a name that points at an internal
+// address (or is changed to, after submission) cannot be reached
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+// Wait before attempt `attempt` (>= 2): 1s, 2s, 4s, ... capped at MAX_BACKOFF
+fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(1u32.checked_shl(attempt - 2).unwrap_or(u32::MAX))
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::dns::Resolve;
+    use std::str::FromStr;
+
+    #[test]
+    fn internal_callback_urls_are_rejected() {
+        for url in [
+            "ftp://example.com/hook",
+            "http://localhost:8080/hook",
+            "http://api.localhost/hook",
+            "http://127.0.0.1/hook",
+            // Alternative spellings of 127.0.0.1 are normalized before the check
+            "http://2130706433/hook",
+            "http://0x7f.1/hook",
+            "http://0.0.0.0/hook",
+            "http://10.1.2.3/hook",
+            "http://172.16.0.1/hook",
+            "http://192.168.1.1/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/hook",
+            "http://224.0.0.1/hook",
+            "http://255.255.255.255/hook",
+            "http://[::1]/hook",
+            "http://[::]/hook",
+            "http://[fe80::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[ff02::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://[::ffff:a9fe:a9fe]/hook",
+            "http://[::7f00:1]/hook",
+            "http://[::a9fe:a9fe]/hook",
+            "http://[64:ff9b::7f00:1]/hook",
+            "http://[64:ff9b::a00:1]/hook",
+            "http://[64:ff9b:1::5db8:d822]/hook",
+            "http://[2002:7f00:1::]/hook",
+            "http://[2002:c0a8:101::1]/hook",
+            "http://[fec0::1]/hook",
+        ] {
+            assert!(parse_callback_url(url).is_err(), "{} was accepted", url);
+        }
+
+        for url in [
+            "https://example.com/hook",
+            "http://93.184.216.34:8080/hook",
+            "http://[2606:2800:220:1:248:1893:25c8:1946]/hook",
+            // Public IPv4 hosts behind NAT64 and 6to4 are fine
+            "http://[64:ff9b::5db8:d822]/hook",
+            "http://[2002:5db8:d822::1]/hook",
+        ] {
+            assert!(parse_callback_url(url).is_ok(), "{} was rejected", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn names_resolving_to_internal_addresses_are_rejected() {
+        let name = reqwest::dns::Name::from_str("localhost").unwrap();
+        assert!(PublicResolver.resolve(name).await.is_err());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(2), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(2));
+        assert_eq!(backoff(4), Duration::from_secs(4));
+        assert_eq!(backoff(10), Duration::from_secs(256));
+        assert_eq!(backoff(11), MAX_BACKOFF);
+        // Shifts past the width of u32 saturate instead of wrapping
+        assert_eq!(backoff(40), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+}